@@ -43,7 +43,7 @@ fn find_best(line: &[u32]) -> Option<(u32, u32)> {
             }
         }
     }
-    return None;
+    None
 }
 
 fn compute(lines: &[Vec<u32>]) -> u32 {
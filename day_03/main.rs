@@ -1,30 +1,67 @@
-use std::cmp;
+use std::{cmp, env, io};
+use std::fs::File;
+use std::io::Read;
+use std::process::exit;
 use core::fmt;
 
+/// A point on one of the lattices the spiral can be walked on.
+trait PositionTrait: Clone + Eq + fmt::Debug {
+    /// Where the spiral starts.
+    fn origin() -> Self;
+    /// Number of steps needed to walk from `self` to `other` on the lattice.
+    fn distance(&self, other: &Self) -> u32;
+    /// Index of the shell `other` is on when the spiral is centred on `self`.
+    fn shell_distance(&self, other: &Self) -> u32;
+    fn min(&self, other: &Self) -> Self;
+    fn max(&self, other: &Self) -> Self;
+    /// Number of cells along each axis of the box from `min` to `max`.
+    fn span(min: &Self, max: &Self) -> Self;
+    /// All the positions on shell `radius` around the origin, in spiral order.
+    fn shell(radius: u32) -> Vec<Self>;
+    fn coordinates(&self) -> Vec<i32>;
+
+    fn adjacent(&self, other: &Self) -> bool {
+        self.shell_distance(other) <= 1
+    }
+}
+
+/// Walk the shells of a lattice outward from the origin.
+fn spiral<P: PositionTrait>() -> impl Iterator<Item=P> {
+    (0..).flat_map(P::shell)
+}
+
+/// Square lattice, the one from the puzzle.
 #[derive(Clone, PartialEq, Eq)]
 struct Position {
     x: i32,
     y: i32,
 }
 
-trait PositionTrait {
-    fn new(x: i32, y: i32) -> Self;
-    fn manhattan_distance(self, other: &Self) -> u32;
-    fn min(&self, other: &Self) -> Position;
-    fn max(&self, other: &Self) -> Position;
-}
-
 impl Position {
     fn new(x: i32, y: i32) -> Self {
         Position {x, y}
     }
 
-    fn adjacent(&self, other: &Self) -> bool {
-        (self.x - other.x).abs() <= 1 && (self.y - other.y).abs() <= 1
+    fn manhattan_distance(&self, other: &Self) -> u32 {
+        ((self.x - other.x).abs() + (self.y - other.y).abs()) as u32
     }
 
-    fn manhattan_distance(self, other: &Self) -> u32 {
-        ((self.x - other.x).abs() + (self.y - other.y).abs()) as u32
+    fn chebyshev_distance(&self, other: &Self) -> u32 {
+        cmp::max((self.x - other.x).abs(), (self.y - other.y).abs()) as u32
+    }
+}
+
+impl PositionTrait for Position {
+    fn origin() -> Self {
+        Position::new(0, 0)
+    }
+
+    fn distance(&self, other: &Self) -> u32 {
+        self.manhattan_distance(other)
+    }
+
+    fn shell_distance(&self, other: &Self) -> u32 {
+        self.chebyshev_distance(other)
     }
 
     fn min(&self, other: &Self) -> Position {
@@ -41,31 +78,189 @@ impl Position {
         }
     }
 
-    fn up() -> Position {
-        Position::new(0, 1)
+    fn span(min: &Self, max: &Self) -> Self {
+        Position::new(max.x - min.x + 1, max.y - min.y + 1)
+    }
+
+    fn shell(radius: u32) -> Vec<Self> {
+        if radius == 0 {
+            return vec!(Position::origin());
+        }
+        let r = radius as i32;
+        let mut rv = Vec::with_capacity(8 * radius as usize);
+        // start just above the bottom right corner and go up, left, down and right
+        let mut current = Position::new(r, -r);
+        for (dx, dy) in &[(0, 1), (-1, 0), (0, -1), (1, 0)] {
+            for _ in 0..2 * r {
+                current = Position::new(current.x + dx, current.y + dy);
+                rv.push(current.clone());
+            }
+        }
+        rv
     }
 
-    fn down() -> Position {
-        Position::new(0, -1)
+    fn coordinates(&self) -> Vec<i32> {
+        vec!(self.x, self.y)
     }
+}
+
+/// Hexagonal lattice using axial coordinates.
+#[derive(Clone, PartialEq, Eq)]
+struct HexPosition {
+    q: i32,
+    r: i32,
+}
 
-    fn right() -> Position {
-        Position::new(1, 0)
+impl HexPosition {
+    // the 6 neighbours, going counter-clockwise from the one on the right
+    const DIRECTIONS: [(i32, i32); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+
+    fn new(q: i32, r: i32) -> Self {
+        HexPosition {q, r}
     }
 
-    fn left() -> Position {
-        Position::new(-1, 0)
+    fn hex_distance(&self, other: &Self) -> u32 {
+        let dq = self.q - other.q;
+        let dr = self.r - other.r;
+        cmp::max(cmp::max(dq.abs(), dr.abs()), (dq + dr).abs()) as u32
     }
 }
 
+impl PositionTrait for HexPosition {
+    fn origin() -> Self {
+        HexPosition::new(0, 0)
+    }
+
+    fn distance(&self, other: &Self) -> u32 {
+        self.hex_distance(other)
+    }
+
+    fn shell_distance(&self, other: &Self) -> u32 {
+        self.hex_distance(other)
+    }
+
+    fn min(&self, other: &Self) -> Self {
+        HexPosition::new(cmp::min(self.q, other.q), cmp::min(self.r, other.r))
+    }
+
+    fn max(&self, other: &Self) -> Self {
+        HexPosition::new(cmp::max(self.q, other.q), cmp::max(self.r, other.r))
+    }
+
+    fn span(min: &Self, max: &Self) -> Self {
+        HexPosition::new(max.q - min.q + 1, max.r - min.r + 1)
+    }
+
+    fn shell(radius: u32) -> Vec<Self> {
+        if radius == 0 {
+            return vec!(HexPosition::origin());
+        }
+        let k = radius as i32;
+        let mut rv = Vec::with_capacity(6 * radius as usize);
+        // start from the corner on the right, walking each side towards the next corner
+        // means the ring finishes on that corner, next to where the next ring starts.
+        let mut current = HexPosition::new(k, 0);
+        for side in 0..6 {
+            let (dq, dr) = Self::DIRECTIONS[(side + 2) % 6];
+            for _ in 0..k {
+                current = HexPosition::new(current.q + dq, current.r + dr);
+                rv.push(current.clone());
+            }
+        }
+        rv
+    }
+
+    fn coordinates(&self) -> Vec<i32> {
+        vec!(self.q, self.r)
+    }
+}
+
+/// Cubic lattice, shells are the surfaces of cubes.
 #[derive(Clone, PartialEq, Eq)]
-struct Cell {
-    position: Position,
+struct CubePosition {
+    x: i32,
+    y: i32,
+    z: i32,
+}
+
+impl CubePosition {
+    fn new(x: i32, y: i32, z: i32) -> Self {
+        CubePosition {x, y, z}
+    }
+
+    fn manhattan_distance(&self, other: &Self) -> u32 {
+        ((self.x - other.x).abs() + (self.y - other.y).abs() + (self.z - other.z).abs()) as u32
+    }
+
+    fn chebyshev_distance(&self, other: &Self) -> u32 {
+        cmp::max(
+            cmp::max((self.x - other.x).abs(), (self.y - other.y).abs()),
+            (self.z - other.z).abs(),
+        ) as u32
+    }
+}
+
+impl PositionTrait for CubePosition {
+    fn origin() -> Self {
+        CubePosition::new(0, 0, 0)
+    }
+
+    fn distance(&self, other: &Self) -> u32 {
+        self.manhattan_distance(other)
+    }
+
+    fn shell_distance(&self, other: &Self) -> u32 {
+        self.chebyshev_distance(other)
+    }
+
+    fn min(&self, other: &Self) -> Self {
+        CubePosition::new(
+            cmp::min(self.x, other.x),
+            cmp::min(self.y, other.y),
+            cmp::min(self.z, other.z),
+        )
+    }
+
+    fn max(&self, other: &Self) -> Self {
+        CubePosition::new(
+            cmp::max(self.x, other.x),
+            cmp::max(self.y, other.y),
+            cmp::max(self.z, other.z),
+        )
+    }
+
+    fn span(min: &Self, max: &Self) -> Self {
+        CubePosition::new(max.x - min.x + 1, max.y - min.y + 1, max.z - min.z + 1)
+    }
+
+    fn shell(radius: u32) -> Vec<Self> {
+        let r = radius as i32;
+        let mut rv = Vec::new();
+        // bottom to top: the caps are full squares, in between only the square's ring.
+        for z in -r..=r {
+            let layer: Vec<Position> = if z.abs() == r {
+                spiral().take(((2 * r + 1) * (2 * r + 1)) as usize).collect()
+            } else {
+                Position::shell(radius)
+            };
+            rv.extend(layer.iter().map(|p| CubePosition::new(p.x, p.y, z)));
+        }
+        rv
+    }
+
+    fn coordinates(&self) -> Vec<i32> {
+        vec!(self.x, self.y, self.z)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+struct Cell<P = Position> {
+    position: P,
     value: u64,
 }
 
-impl Cell {
-    fn new(position: Position, value: u64) -> Self {
+impl<P: PositionTrait> Cell<P> {
+    fn new(position: P, value: u64) -> Self {
         Cell{
             position,
             value,
@@ -79,123 +274,242 @@ impl fmt::Debug for Position {
     }
 }
 
-impl fmt::Debug for Cell {
+impl fmt::Debug for HexPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(q={}, r={})", self.q, self.r)
+    }
+}
+
+impl fmt::Debug for CubePosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {}, {})", self.x, self.y, self.z)
+    }
+}
+
+impl<P: fmt::Debug> fmt::Debug for Cell<P> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?} [{}]", self.position, self.value)
     }
 }
 
-struct Grid {
-    grid: Vec<Cell>,
-    size: Position,
+struct Grid<P = Position> {
+    grid: Vec<Cell<P>>,
+    size: P,
 }
 
-impl Grid {
-    fn empty() -> Grid {
+impl<P: PositionTrait> Grid<P> {
+    fn empty() -> Self {
         Grid{
-            grid: vec!(Cell::new(Position::new(0, 0), 1)),
-            size: Position{x:1, y:1},
+            grid: vec!(Cell::new(P::origin(), 1)),
+            size: P::span(&P::origin(), &P::origin()),
         }
     }
 
-    fn get(&self, i: u32) -> Option<Cell> {
-        let d = self.grid.get((i - 1) as usize);
-        if d.is_none() {
-            None
-        } else {
-            Some((*d.unwrap()).clone())
-        }
+    fn get(&self, i: u32) -> Option<Cell<P>> {
+        self.grid.get((i - 1) as usize).cloned()
     }
 
-    fn sum_adjacent(&self, position: &Position) -> u64 {
+    fn sum_adjacent(&self, position: &P) -> u64 {
         let mut sum = 0;
         for cell in &self.grid {
             if cell.position.adjacent(position) {
-                eprintln!("Adding {} to {}", cell.value, sum);
                 sum += cell.value;
             }
         }
         sum
     }
 
-    fn new(until: u32) -> Grid {
-        let mut grid = Grid::empty();
-        let mut i: u32 = 2;
-        let mut current= grid.grid[0].position.clone();
-        let mut direction = Position::right();
+    fn new(until: u32) -> Self {
+        let mut grid = Self::empty();
 
-        let mut min_pos = Position{x:0, y:0};
-        let mut max_pos = Position{x:0, y:0};
+        let mut min_pos = P::origin();
+        let mut max_pos = P::origin();
         let mut found = false;
 
-        while i <= until {
-            let next = Position{
-                x:current.x + direction.x,
-                y:current.y + direction.y,
-            };
-
-            if next.x > max_pos.x {
-                grid.size.x += 1;  // adding a col on the right
-                direction = Position::up();  // we were going right, now we go up
-            } else if next.y > max_pos.y {
-                grid.size.y += 1;  // adding a row up
-                direction = Position::left();  // we were going up now we go left
-            } else if next.x < min_pos.x {
-                grid.size.x += 1;  // adding a col on the left
-                direction = Position::down();  // we were going left, now we go down
-            } else if next.y < min_pos.y {
-                grid.size.y += 1;  // adding a row down
-                direction = Position::right();  // we were going down, now we go right
-            }
-
+        for (next, _) in spiral::<P>().skip(1).zip(2..=until) {
             min_pos = min_pos.min(&next);
             max_pos = max_pos.max(&next);
-            current = next.clone();
+            grid.size = P::span(&min_pos, &max_pos);
             // I have to do this because for large enough numbers we overflow a u64...
             // because I have a bug or it's a trap.
             let value = if !found {
                 let value = grid.sum_adjacent(&next);
-
-                if value > until as u64 {
-                    println!("First value > {} is {}", until, value);
-                    found = true;
-                }
+                found = value > until as u64;
                 value
             } else {
                 0
             };
 
             grid.grid.push(Cell::new(next, value));
-
-            i += 1;
         }
 
         grid
     }
+
+    // Keep writing values past the end of the grid until one is above `threshold`.
+    fn stress_test(threshold: u32) -> Vec<u64> {
+        let mut grid = Self::empty();
+
+        for next in spiral::<P>().skip(1) {
+            let value = grid.sum_adjacent(&next);
+            grid.grid.push(Cell::new(next, value));
+            if value > threshold as u64 {
+                break;
+            }
+        }
+        grid.grid.iter().map(|c| c.value).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lattice {
+    Square,
+    Hex,
+    Cube,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Table,
+    Json,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Options {
+    lattice: Lattice,
+    format: Format,
+    indices: Vec<u32>,
+}
+
+impl Options {
+    const USAGE: &'static str =
+        "usage: day_03 [--lattice square|hex|cube] [--json] [--file PATH] [INDEX...]";
+
+    fn from_args(args: &[String]) -> Result<Options, String> {
+        let mut rv = Options{
+            lattice: Lattice::Square,
+            format: Format::Table,
+            indices: Vec::new(),
+        };
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--lattice" => {
+                    rv.lattice = match args.next().map(|s| s.as_str()) {
+                        Some("square") => Lattice::Square,
+                        Some("hex") => Lattice::Hex,
+                        Some("cube") => Lattice::Cube,
+                        other => return Err(format!("unknown lattice {:?}", other)),
+                    };
+                },
+                "--json" => rv.format = Format::Json,
+                "--file" => {
+                    let path = args.next().ok_or("--file needs a path")?;
+                    let content = read(path).map_err(|e| format!("{}: {}", path, e))?;
+                    for value in content.split_whitespace() {
+                        rv.indices.push(parse_index(value)?);
+                    }
+                },
+                value => rv.indices.push(parse_index(value)?),
+            }
+        }
+
+        if rv.indices.is_empty() {
+            rv.indices.push(347991);
+        }
+        Ok(rv)
+    }
+}
+
+fn parse_index(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(0) => Err("indices start at 1".to_string()),
+        Ok(i) => Ok(i),
+        Err(_) => Err(format!("{} is not an index", value)),
+    }
+}
+
+fn read(path: &str) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Report {
+    index: u32,
+    coordinates: Vec<i32>,
+    position: String,
+    distance: u32,
+    first_above: u64,
+}
+
+impl Report {
+    fn to_json(&self) -> String {
+        let coordinates: Vec<String> = self.coordinates.iter().map(|c| c.to_string()).collect();
+        format!(
+            "{{\"index\": {}, \"position\": [{}], \"distance\": {}, \"first_above\": {}}}",
+            self.index,
+            coordinates.join(", "),
+            self.distance,
+            self.first_above,
+        )
+    }
+}
+
+fn query<P: PositionTrait>(indices: &[u32]) -> Vec<Report> {
+    let until = indices.iter().max().cloned().unwrap_or(1);
+    let grid: Grid<P> = Grid::new(until);
+    eprintln!("Created a grid of {:?}", grid.size);
+    let o = grid.get(1).unwrap();
+    let values = Grid::<P>::stress_test(until);
+
+    indices.iter().map(|&index| {
+        let t = grid.get(index).unwrap();
+        Report{
+            index,
+            coordinates: t.position.coordinates(),
+            position: format!("{:?}", t.position),
+            distance: t.position.distance(&o.position),
+            first_above: *values.iter().find(|&&v| v > index as u64).unwrap(),
+        }
+    }).collect()
+}
+
+fn print_reports(reports: &[Report], format: Format) {
+    match format {
+        Format::Table => {
+            println!("{:>10} {:>20} {:>10} {:>12}", "index", "position", "distance", "first above");
+            for r in reports {
+                println!("{:>10} {:>20} {:>10} {:>12}", r.index, r.position, r.distance, r.first_above);
+            }
+        },
+        Format::Json => {
+            let entries: Vec<String> = reports.iter().map(|r| format!("  {}", r.to_json())).collect();
+            println!("[\n{}\n]", entries.join(",\n"));
+        },
+    }
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().skip(1).collect();
-    let input: u32 = if args.len() > 0 {
-        args[1].parse().expect("not an integer")
-    } else {
-        347991
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match Options::from_args(&args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("{}", Options::USAGE);
+            exit(1);
+        },
     };
-    let grid = Grid::new(input);
-    println!("Created a grid of {:?}", grid.size);
-    let o = grid.get(1).unwrap();
-    let t = grid.get(input).unwrap();
-    println!("Distance between {} and 1 is {}",
-        input,
-        t.position.manhattan_distance(&o.position),
-    );
-
-    // for cell in &grid.grid {
-    //     if cell.value > input as u64 {
-    //         println!("First value > {} is {}", input, cell.value);
-    //         break;
-    //     }
-    // }
+
+    let reports = match options.lattice {
+        Lattice::Square => query::<Position>(&options.indices),
+        Lattice::Hex => query::<HexPosition>(&options.indices),
+        Lattice::Cube => query::<CubePosition>(&options.indices),
+    };
+    print_reports(&reports, options.format);
 }
 
 
@@ -265,4 +579,142 @@ mod tests {
     fn test_adjascent(a: &Position, b: &Position, expected: bool) {
         assert!(a.adjacent(b) == expected);
     }
+
+    #[rstest(a, b, manhattan, chebyshev,
+    case(&Position::new(0, 0), &Position::new(0, 0), 0, 0),
+    case(&Position::new(0, 0), &Position::new(1, 1), 2, 1),
+    case(&Position::new(0, 0), &Position::new(-3, 2), 5, 3),
+    case(&Position::new(2, 0), &Position::new(-2, 0), 4, 4),
+    )]
+    fn test_square_distance(a: &Position, b: &Position, manhattan: u32, chebyshev: u32) {
+        assert_eq!(a.distance(b), manhattan);
+        assert_eq!(a.shell_distance(b), chebyshev);
+    }
+
+    #[rstest(a, b, expected,
+    case(&HexPosition::new(0, 0), &HexPosition::new(0, 0), 0),
+    case(&HexPosition::new(0, 0), &HexPosition::new(1, -1), 1),
+    case(&HexPosition::new(0, 0), &HexPosition::new(1, 1), 2),
+    case(&HexPosition::new(0, 0), &HexPosition::new(3, -1), 3),
+    case(&HexPosition::new(-2, 1), &HexPosition::new(2, -1), 4),
+    )]
+    fn test_hex_distance(a: &HexPosition, b: &HexPosition, expected: u32) {
+        assert_eq!(a.distance(b), expected);
+    }
+
+    #[rstest(a, b, manhattan, chebyshev,
+    case(&CubePosition::new(0, 0, 0), &CubePosition::new(1, 1, 1), 3, 1),
+    case(&CubePosition::new(0, 0, 0), &CubePosition::new(-2, 1, 3), 6, 3),
+    )]
+    fn test_cube_distance(a: &CubePosition, b: &CubePosition, manhattan: u32, chebyshev: u32) {
+        assert_eq!(a.distance(b), manhattan);
+        assert_eq!(a.shell_distance(b), chebyshev);
+    }
+
+    fn check_shell<P: PositionTrait>(radius: u32, expected_len: usize) {
+        let shell = P::shell(radius);
+        assert_eq!(shell.len(), expected_len);
+        for (idx, p) in shell.iter().enumerate() {
+            assert_eq!(P::origin().shell_distance(p), radius, "{:?}", p);
+            assert!(!shell[idx + 1..].contains(p), "{:?} is there twice", p);
+        }
+    }
+
+    #[rstest(radius,
+    case(0),
+    case(1),
+    case(2),
+    case(5),
+    )]
+    fn test_shells(radius: u32) {
+        let r = radius as usize;
+        check_shell::<Position>(radius, if r == 0 { 1 } else { 8 * r });
+        check_shell::<HexPosition>(radius, if r == 0 { 1 } else { 6 * r });
+        check_shell::<CubePosition>(radius, (2 * r + 1).pow(3) - (2 * r).saturating_sub(1).pow(3));
+    }
+
+    #[test]
+    fn test_spiral_is_continuous() {
+        // every step of the 2D spirals goes to a neighbour
+        let square: Vec<Position> = spiral().take(200).collect();
+        for w in square.windows(2) {
+            assert_eq!(w[0].distance(&w[1]), 1, "{:?}", w);
+        }
+        let hex: Vec<HexPosition> = spiral().take(200).collect();
+        for w in hex.windows(2) {
+            assert_eq!(w[0].distance(&w[1]), 1, "{:?}", w);
+        }
+    }
+
+    #[rstest(input, expected_values,
+    case(1, &[1]),
+    case(7, &[1, 1, 2, 3, 4, 5, 7]),  // the last of ring 1 touches the first one too
+    case(8, &[1, 1, 2, 3, 4, 5, 7, 8]),  // ring 2 starts next to the end of ring 1
+    )]
+    fn test_hex_grid_new(input: u32, expected_values: &[u64]) {
+        let grid: Grid<HexPosition> = Grid::new(input);
+        let values: Vec<u64> = grid.grid.iter().map(|c| c.value).collect();
+        assert_eq!(values, expected_values);
+    }
+
+    #[test]
+    fn test_cube_grid_new() {
+        // the first shell is 26 cells, all of them are next to the origin
+        let grid: Grid<CubePosition> = Grid::new(27);
+        assert_eq!(grid.size, CubePosition::new(3, 3, 3));
+        assert_eq!(grid.get(27).unwrap().position.shell_distance(&CubePosition::origin()), 1);
+    }
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[rstest(args, lattice, format, indices,
+    case(&[], Lattice::Square, Format::Table, &[347991]),
+    case(&["12"], Lattice::Square, Format::Table, &[12]),  // the first argument is used
+    case(&["12", "23", "1024"], Lattice::Square, Format::Table, &[12, 23, 1024]),
+    case(&["--lattice", "hex", "--json", "5"], Lattice::Hex, Format::Json, &[5]),
+    case(&["5", "--lattice", "cube"], Lattice::Cube, Format::Table, &[5]),
+    )]
+    fn test_options(args: &[&str], lattice: Lattice, format: Format, indices: &[u32]) {
+        let options = Options::from_args(&to_args(args)).unwrap();
+        assert_eq!(options, Options{lattice, format, indices: indices.to_vec()});
+    }
+
+    #[rstest(args,
+    case(&["0"]),
+    case(&["twelve"]),
+    case(&["--lattice", "triangle"]),
+    case(&["--lattice"]),
+    case(&["--file"]),
+    case(&["--file", "day_03/does_not_exist.txt"]),
+    )]
+    fn test_options_error(args: &[&str]) {
+        assert!(Options::from_args(&to_args(args)).is_err());
+    }
+
+    #[rstest(index, distance, first_above,
+    case(1, 0, 2),
+    case(12, 3, 23),
+    case(23, 2, 25),
+    case(747, 18, 806),
+    case(1024, 31, 1968),
+    )]
+    fn test_query(index: u32, distance: u32, first_above: u64) {
+        // a single grid is used for all the indices
+        let reports = query::<Position>(&[1, 12, 23, 747, 1024]);
+        let report = reports.iter().find(|r| r.index == index).unwrap();
+        assert_eq!(report.distance, distance);
+        assert_eq!(report.first_above, first_above);
+    }
+
+    #[test]
+    fn test_report_json() {
+        let report = &query::<CubePosition>(&[2])[0];
+        assert_eq!(
+            report.to_json(),
+            format!("{{\"index\": 2, \"position\": {:?}, \"distance\": {}, \"first_above\": 4}}",
+                    report.coordinates, report.distance),
+        );
+    }
 }
@@ -0,0 +1,213 @@
+use std::{io, str};
+use std::io::BufRead;
+
+use crate::policy::{PassphrasePolicy, NoAnagrams, NoDuplicates};
+
+/// Which of the puzzle's policies to check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    Duplicates,
+    Anagrams,
+}
+
+/// The word itself, 5 bits per letter so up to 25 lower case ASCII letters.
+fn word_signature(word: &[u8]) -> Option<u128> {
+    if word.len() > 25 {
+        return None;
+    }
+    let mut rv: u128 = 0;
+    for &b in word {
+        if !b.is_ascii_lowercase() {
+            return None;
+        }
+        // no letter is 0 so words of different lengths cannot collide
+        rv = rv << 5 | (b - b'a' + 1) as u128;
+    }
+    Some(rv)
+}
+
+/// How many times each lower case ASCII letter is in the word, 4 bits per letter.
+fn letter_counts(word: &[u8]) -> Option<u128> {
+    let mut rv: u128 = 0;
+    for &b in word {
+        if !b.is_ascii_lowercase() {
+            return None;
+        }
+        let shift = (b - b'a') as u32 * 4;
+        if (rv >> shift) & 0xf == 0xf {
+            return None;
+        }
+        rv += 1 << shift;
+    }
+    Some(rv)
+}
+
+/// Checks passphrases without allocating once it has warmed up.
+///
+/// Words are turned into `u128` signatures and compared in a scratch buffer kept between
+/// phrases. Phrases with words that cannot be packed (not lower case ASCII, too long or with
+/// too many times the same letter) go through the slower `policy` checks instead.
+pub struct Validator {
+    scratch: Vec<u128>,
+}
+
+impl Validator {
+    pub fn new() -> Validator {
+        Validator{
+            scratch: Vec::with_capacity(32),
+        }
+    }
+
+    /// Only fails if the phrase needs the policies and is not valid UTF-8.
+    pub fn is_valid(&mut self, phrase: &[u8], rule: Rule) -> Result<bool, str::Utf8Error> {
+        if let Some(valid) = self.check_signatures(phrase, rule) {
+            return Ok(valid);
+        }
+        let phrase = str::from_utf8(phrase)?;
+        Ok(match rule {
+            Rule::Duplicates => NoDuplicates.is_valid(phrase),
+            Rule::Anagrams => NoAnagrams.is_valid(phrase),
+        })
+    }
+
+    // None when a word has no signature.
+    // Works on bytes: any non ASCII byte means there is no signature so there is no need to
+    // decode the phrase, and the ASCII whitespaces are the same as for `split_whitespace`.
+    fn check_signatures(&mut self, phrase: &[u8], rule: Rule) -> Option<bool> {
+        self.scratch.clear();
+        let words = phrase
+            .split(|&b| b == b' ' || (b'\t'..=b'\r').contains(&b))
+            .filter(|w| !w.is_empty());
+        for word in words {
+            let signature = match rule {
+                Rule::Duplicates => word_signature(word)?,
+                Rule::Anagrams => letter_counts(word)?,
+            };
+            self.scratch.push(signature);
+        }
+        // passphrases are short: sorting beats hashing
+        self.scratch.sort_unstable();
+        Some(self.scratch.windows(2).all(|w| w[0] != w[1]))
+    }
+
+    /// Count the lines of `reader` that are valid under each rule.
+    /// Returns the number of lines followed by the counts in the same order as `rules`.
+    pub fn count_valid<R: BufRead>(&mut self, mut reader: R, rules: &[Rule]) -> io::Result<(usize, Vec<usize>)> {
+        let mut line = Vec::new();
+        let mut total = 0;
+        let mut valid = vec![0; rules.len()];
+
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            let mut phrase = line.as_slice();
+            while let Some((b'\n', rest)) | Some((b'\r', rest)) = phrase.split_last() {
+                phrase = rest;
+            }
+            for (count, &rule) in valid.iter_mut().zip(rules) {
+                if self.is_valid(phrase, rule).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))? {
+                    *count += 1;
+                }
+            }
+            total += 1;
+            line.clear();
+        }
+        Ok((total, valid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Random;
+    use rstest::rstest;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    #[rstest(a, b, same_word, same_letters,
+    case("abc", "abc", true, true),
+    case("abc", "cab", false, true),
+    case("a", "aa", false, false),
+    case("z", "az", false, false),
+    case("abcdefghijklmnopqrstuvwxy", "abcdefghijklmnopqrstuvwyx", false, true),
+    case("zzzzzzzzzzzzzzz", "zzzzzzzzzzzzzzy", false, false),
+    )]
+    fn test_signatures(a: &str, b: &str, same_word: bool, same_letters: bool) {
+        let (a, b) = (a.as_bytes(), b.as_bytes());
+        assert_eq!(word_signature(a).unwrap() == word_signature(b).unwrap(), same_word);
+        assert_eq!(letter_counts(a).unwrap() == letter_counts(b).unwrap(), same_letters);
+    }
+
+    #[rstest(word, has_word_signature, has_letter_counts,
+    case("", true, true),
+    case("Abc", false, false),
+    case("caf\u{e9}", false, false),
+    case("abcdefghijklmnopqrstuvwxy", true, true),
+    case("abcdefghijklmnopqrstuvwxyz", false, true),
+    case("aaaaaaaaaaaaaaa", true, true),
+    case("aaaaaaaaaaaaaaaa", true, false),  // 16 a
+    )]
+    fn test_no_signature(word: &str, has_word_signature: bool, has_letter_counts: bool) {
+        assert_eq!(word_signature(word.as_bytes()).is_some(), has_word_signature);
+        assert_eq!(letter_counts(word.as_bytes()).is_some(), has_letter_counts);
+    }
+
+    #[rstest(passphrase,
+    case("aa bb cc dd ee"),
+    case("aa bb cc dd aa"),
+    case("aa bb cc dd aaa"),
+    case("abcde xyz ecdab"),
+    case("a ab abc abd abf abj"),
+    case("iiii oiii ooii oooi oooo"),
+    case("oiii ioii iioi iiio"),
+    case("aaaaaaaaaaaaaaaab baaaaaaaaaaaaaaaa"),  // too many a
+    case("Ab bA"),
+    case("caf\u{e9} face\u{301}"),
+    case(""),
+    case("  aa \t aa  "),
+    case("aa\x0baa"),  // vertical tab
+    case("aa\u{a0}aa"),  // no-break space
+    )]
+    fn test_same_as_policies(passphrase: &str) {
+        let mut validator = Validator::new();
+        let phrase = passphrase.as_bytes();
+        assert_eq!(validator.is_valid(phrase, Rule::Duplicates), Ok(NoDuplicates.is_valid(passphrase)));
+        assert_eq!(validator.is_valid(phrase, Rule::Anagrams), Ok(NoAnagrams.is_valid(passphrase)));
+    }
+
+    #[test]
+    fn test_same_as_policies_generated() {
+        // small alphabet and short words to get plenty of collisions
+        let mut validator = Validator::new();
+        let mut random = Random::new(2017);
+        for _ in 0..2000 {
+            let mut phrase = String::new();
+            for _ in 0..random.below(16) {
+                for _ in 0..random.between(&(1..=4)) {
+                    phrase.push((b'a' + random.below(4) as u8) as char);
+                }
+                phrase.push(' ');
+            }
+            let bytes = phrase.as_bytes();
+            assert_eq!(validator.is_valid(bytes, Rule::Duplicates), Ok(NoDuplicates.is_valid(&phrase)), "{}", phrase);
+            assert_eq!(validator.is_valid(bytes, Rule::Anagrams), Ok(NoAnagrams.is_valid(&phrase)), "{}", phrase);
+        }
+    }
+
+    #[test]
+    fn test_count_valid() {
+        let reader = BufReader::new(File::open("day_04/input.txt").unwrap());
+        let rv = Validator::new().count_valid(reader, &[Rule::Duplicates, Rule::Anagrams]).unwrap();
+        assert_eq!(rv, (512, vec!(466, 251)));
+
+        let reader = "aa bb\r\naa aa\n\nab ba".as_bytes();
+        let rv = Validator::new().count_valid(reader, &[Rule::Anagrams]).unwrap();
+        assert_eq!(rv, (4, vec!(2)));
+
+        // the non ASCII lines go through the policies
+        let reader = "caf\u{e9} face\u{301}\n\u{e9}a ae\u{3000}aa".as_bytes();
+        let rv = Validator::new().count_valid(reader, &[Rule::Duplicates, Rule::Anagrams]).unwrap();
+        assert_eq!(rv, (2, vec!(2, 1)));
+
+        let reader = &b"aa bb\n\xffaa bb"[..];
+        assert!(Validator::new().count_valid(reader, &[Rule::Anagrams]).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use std::ops::RangeInclusive;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::policy::{NoDuplicates, PassphrasePolicy};
+use crate::random::Random;
+
+// how many phrases to try before giving up on the policy
+const ATTEMPTS: usize = 1000;
+
+/// Where the words come from.
+#[derive(Debug)]
+pub enum Source {
+    Words(Vec<String>),
+    Letters {
+        letters: Vec<char>,
+        length: RangeInclusive<usize>,
+    },
+}
+
+/// What kind of passphrase to generate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Valid,
+    /// a word is repeated
+    Duplicate,
+    /// a word is an anagram of another one
+    Anagram,
+}
+
+pub struct Generator {
+    source: Source,
+    words: RangeInclusive<usize>,
+    policy: Box<dyn PassphrasePolicy>,
+    random: Random,
+}
+
+impl Generator {
+    pub fn new(source: Source, words: RangeInclusive<usize>, policy: Box<dyn PassphrasePolicy>, seed: u64) -> Generator {
+        Generator{
+            source,
+            words,
+            policy,
+            random: Random::new(seed),
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let random = &mut self.random;
+        match &self.source {
+            Source::Words(list) => list[random.below(list.len())].clone(),
+            Source::Letters { letters, length } => {
+                let len = random.between(length);
+                (0..len).map(|_| letters[random.below(letters.len())]).collect()
+            },
+        }
+    }
+
+    fn phrase(&mut self) -> Vec<String> {
+        let len = self.random.between(&self.words.clone());
+        (0..len).map(|_| self.word()).collect()
+    }
+
+    // Put a copy of a word, or its letters in another order, in place of another one. False
+    // for an anagram when no word has two different letters to swap.
+    fn add_defect(&mut self, words: &mut [String], kind: Kind) -> bool {
+        let from = if kind == Kind::Anagram {
+            let candidates: Vec<usize> = (0..words.len())
+                .filter(|&i| {
+                    let mut letters = words[i].graphemes(true);
+                    let first = letters.next();
+                    letters.any(|g| Some(g) != first)
+                })
+                .collect();
+            if candidates.is_empty() {
+                return false;
+            }
+            candidates[self.random.below(candidates.len())]
+        } else {
+            self.random.below(words.len())
+        };
+        let mut to = self.random.below(words.len() - 1);
+        if to >= from {
+            to += 1;
+        }
+        words[to] = match kind {
+            Kind::Anagram => {
+                let mut letters: Vec<&str> = words[from].graphemes(true).collect();
+                // the same order again would be a duplicate
+                loop {
+                    self.random.shuffle(&mut letters);
+                    if letters.concat() != words[from] {
+                        break letters.concat();
+                    }
+                }
+            },
+            _ => words[from].clone(),
+        };
+        true
+    }
+
+    /// A phrase of the given kind, valid or not under the policy.
+    /// None if none was found after a while (the policy may be too strict for the source).
+    pub fn generate(&mut self, kind: Kind) -> Option<String> {
+        for _ in 0..ATTEMPTS {
+            let mut words = self.phrase();
+            if kind != Kind::Valid && (words.len() < 2 || !self.add_defect(&mut words, kind)) {
+                continue;
+            }
+            let phrase = words.join(" ");
+            // an anagram phrase must not have a duplicate, whatever the policy
+            if kind == Kind::Anagram && !NoDuplicates.is_valid(&phrase) {
+                continue;
+            }
+            if self.policy.is_valid(&phrase) == (kind == Kind::Valid) {
+                return Some(phrase);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast::{Rule, Validator};
+    use crate::policy::{parse, NoAnagrams, NoDuplicates};
+    use rstest::rstest;
+
+    fn letters(letters: &str, length: RangeInclusive<usize>) -> Source {
+        Source::Letters {letters: letters.chars().collect(), length}
+    }
+
+    #[test]
+    fn test_generate_is_reproducible() {
+        let mut a = Generator::new(letters("abc", 2..=4), 3..=6, Box::new(NoAnagrams), 42);
+        let mut b = Generator::new(letters("abc", 2..=4), 3..=6, Box::new(NoAnagrams), 42);
+        for _ in 0..20 {
+            assert_eq!(a.generate(Kind::Valid), b.generate(Kind::Valid));
+            assert_eq!(a.generate(Kind::Anagram), b.generate(Kind::Anagram));
+        }
+    }
+
+    #[rstest(seed,
+    case(0),
+    case(1),
+    case(2017),
+    )]
+    fn test_generated_phrases(seed: u64) {
+        // small alphabet so that the policy actually has to reject phrases
+        let mut generator = Generator::new(letters("abcd", 1..=3), 2..=6, Box::new(NoAnagrams), seed);
+        let mut validator = Validator::new();
+
+        for _ in 0..200 {
+            let phrase = generator.generate(Kind::Valid).unwrap();
+            let words = phrase.split_whitespace().count();
+            assert!((2..=6).contains(&words), "{}", phrase);
+            assert!(phrase.split_whitespace().all(|w| (1..=3).contains(&w.len()) && w.chars().all(|c| "abcd".contains(c))));
+            assert!(NoAnagrams.is_valid(&phrase), "{}", phrase);
+            assert!(NoDuplicates.is_valid(&phrase), "{}", phrase);
+            assert_eq!(validator.is_valid(phrase.as_bytes(), Rule::Anagrams), Ok(true), "{}", phrase);
+
+            let phrase = generator.generate(Kind::Duplicate).unwrap();
+            assert!(!NoDuplicates.is_valid(&phrase), "{}", phrase);
+            assert_eq!(validator.is_valid(phrase.as_bytes(), Rule::Duplicates), Ok(false), "{}", phrase);
+
+            let phrase = generator.generate(Kind::Anagram).unwrap();
+            assert!(!NoAnagrams.is_valid(&phrase), "{}", phrase);
+            assert!(NoDuplicates.is_valid(&phrase), "{}", phrase);
+            assert_eq!(validator.is_valid(phrase.as_bytes(), Rule::Anagrams), Ok(false), "{}", phrase);
+        }
+    }
+
+    #[test]
+    fn test_generate_from_words() {
+        let words: Vec<String> = ["caf\u{e9}", "the\u{301}", "tea", "eat", "oak"].iter()
+            .map(|w| w.to_string())
+            .collect();
+        let mut generator = Generator::new(Source::Words(words), 3..=3, Box::new(NoAnagrams), 7);
+        for _ in 0..50 {
+            let phrase = generator.generate(Kind::Valid).unwrap();
+            // tea and eat are anagrams
+            assert!(!(phrase.contains("tea") && phrase.contains("eat")), "{}", phrase);
+            let phrase = generator.generate(Kind::Anagram).unwrap();
+            assert!(!NoAnagrams.is_valid(&phrase), "{}", phrase);
+            assert!(NoDuplicates.is_valid(&phrase), "{}", phrase);
+        }
+    }
+
+    #[rstest(expr, source, words, kind,
+    case("no-anagrams", letters("a", 1..=1), 2..=3, Kind::Valid),  // always an anagram
+    case("max-words(3)", letters("xyz", 3..=5), 2..=3, Kind::Duplicate),  // never too long
+    )]
+    fn test_generate_impossible(expr: &str, source: Source, words: RangeInclusive<usize>, kind: Kind) {
+        let mut generator = Generator::new(source, words, parse(expr).unwrap(), 1);
+        assert!(generator.generate(kind).is_none());
+    }
+}
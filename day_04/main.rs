@@ -1,8 +1,19 @@
 use std::{io, env};
 use std::fs::File;
 use std::io::{BufReader, BufRead};
+use std::process::exit;
 use std::collections::HashSet;
-use std::iter::FromIterator;
+
+mod fast;
+mod generator;
+mod policy;
+mod random;
+mod range;
+
+use fast::{Rule, Validator};
+use generator::{Generator, Kind, Source};
+use policy::{PassphrasePolicy, NoAnagrams, Violation, split_words};
+use range::parse_range;
 
 fn read(path: &str) -> io::Result<Vec<String>> {
     let file = File::open(path)?;
@@ -16,90 +27,286 @@ fn read(path: &str) -> io::Result<Vec<String>> {
     Ok(rv)
 }
 
-fn check_duplicates(phrase: &String) -> bool {
-    let mut hash = HashSet::new();
+fn count_valid(policy: &dyn PassphrasePolicy, contents: &[String]) -> usize {
+    contents.iter().filter(|passphrase| policy.is_valid(passphrase)).count()
+}
+
+// Write the phrase with the offending words in brackets followed by the violations.
+fn format_report(line_no: usize, phrase: &str, violations: &[Violation]) -> String {
+    let words = split_words(phrase);
+    let offending: HashSet<usize> = violations.iter()
+        .flat_map(|v| v.words.positions())
+        .map(|p| p.index)
+        .collect();
+    let highlighted: Vec<String> = words.iter().enumerate()
+        .map(|(idx, (_, word))| {
+            if offending.contains(&idx) {
+                format!("[{}]", word)
+            } else {
+                word.to_string()
+            }
+        })
+        .collect();
+
+    let mut rv = format!("line {}: {}", line_no, highlighted.join(" "));
+    for v in violations {
+        let details: Vec<String> = v.words.positions().iter()
+            .map(|p| format!("'{}' (word {}, col {})", words[p.index].1, p.index + 1, p.column + 1))
+            .collect();
+        if details.is_empty() {
+            rv += &format!("\n  {}: {} words", v.rule, words.len());
+        } else {
+            rv += &format!("\n  {}: {}", v.rule, details.join(" and "));
+        }
+    }
+    rv
+}
+
+struct Options {
+    policy: Option<Box<dyn PassphrasePolicy>>,
+    report: bool,
+}
+
+// usage: day_04 PATH [--policy EXPR | --policy-file PATH] [--report]
+impl Options {
+    fn from_args(args: &[String]) -> Result<Options, String> {
+        let mut rv = Options{
+            policy: None,
+            report: false,
+        };
+        let mut args = args.iter();
 
-    for word in phrase.split_whitespace() {
-        if hash.contains(word) {
-            return false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--policy" => {
+                    let expr = args.next().ok_or("--policy needs an expression")?;
+                    rv.policy = Some(policy::parse(expr)?);
+                },
+                "--policy-file" => {
+                    let path = args.next().ok_or("--policy-file needs a path")?;
+                    rv.policy = Some(policy::from_file(path)?);
+                },
+                "--report" => rv.report = true,
+                other => return Err(format!("unknown argument {}", other)),
+            }
         }
-        hash.insert(word);
+        Ok(rv)
     }
+}
 
-    true
+struct GenerateOptions {
+    count: usize,
+    kind: Kind,
+    generator: Generator,
 }
 
-fn check_anagrams(phrase: &String) -> bool {
-    let mut hash = HashSet::new();
+// usage: day_04 --generate COUNT [--kind valid|duplicate|anagram] [--seed N] [--words N|MIN-MAX]
+//               [--wordlist PATH | --letters LETTERS [--length N|MIN-MAX]]
+//               [--policy EXPR | --policy-file PATH]
+impl GenerateOptions {
+    fn from_args(args: &[String]) -> Result<GenerateOptions, String> {
+        let mut count = 10;
+        let mut kind = Kind::Valid;
+        let mut seed = 2017;
+        let mut words = 5..=10;
+        let mut wordlist = None;
+        let mut letters = "a-z".to_string();
+        let mut length = 2..=7;
+        let mut policy: Box<dyn PassphrasePolicy> = Box::new(NoAnagrams);
+        let mut args = args.iter();
 
-    for word in phrase.split_whitespace() {
-        let mut letters: Vec<char> = word.chars().collect();
-        letters.sort_by(|a, b| b.cmp(a));
-        let s = String::from_iter(letters);
-        if hash.contains(&s) {
-            return false;
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--generate" => count = value()?.parse().map_err(|_| "--generate needs a number")?,
+                "--kind" => {
+                    kind = match value()?.as_str() {
+                        "valid" => Kind::Valid,
+                        "duplicate" => Kind::Duplicate,
+                        "anagram" => Kind::Anagram,
+                        other => return Err(format!("unknown kind {}", other)),
+                    };
+                },
+                "--seed" => seed = value()?.parse().map_err(|_| "--seed needs a number")?,
+                "--words" => words = parse_range(value()?)?,
+                "--wordlist" => wordlist = Some(value()?.clone()),
+                "--letters" => letters = value()?.clone(),
+                "--length" => length = parse_range(value()?)?,
+                "--policy" => policy = policy::parse(value()?)?,
+                "--policy-file" => policy = policy::from_file(value()?)?,
+                other => return Err(format!("unknown argument {}", other)),
+            }
         }
-        hash.insert(s);
+
+        let source = match wordlist {
+            Some(path) => {
+                let content = read(&path).map_err(|e| format!("{}: {}", path, e))?;
+                let list: Vec<String> = content.iter()
+                    .flat_map(|line| line.split_whitespace())
+                    .map(|w| w.to_string())
+                    .collect();
+                if list.is_empty() {
+                    return Err(format!("{}: no words", path));
+                }
+                Source::Words(list)
+            },
+            None => {
+                let letters = policy::expand_letters(&letters);
+                if letters.is_empty() || *length.start() == 0 {
+                    return Err("words need at least a letter".to_string());
+                }
+                Source::Letters {letters, length}
+            },
+        };
+        Ok(GenerateOptions{
+            count,
+            kind,
+            generator: Generator::new(source, words, policy, seed),
+        })
     }
+}
 
-    true
+fn generate(args: &[String]) {
+    let mut options = match GenerateOptions::from_args(args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("invalid arguments: {}", e);
+            exit(1);
+        },
+    };
+    for _ in 0..options.count {
+        match options.generator.generate(options.kind) {
+            Some(phrase) => println!("{}", phrase),
+            None => {
+                eprintln!("could not generate a {:?} passphrase with this policy", options.kind);
+                exit(1);
+            },
+        }
+    }
 }
 
 fn main() {
     let path = env::args().nth(1).expect("please supply a path");
+    if path == "--generate" {
+        let args: Vec<String> = env::args().skip(1).collect();
+        generate(&args);
+        return;
+    }
+    let args: Vec<String> = env::args().skip(2).collect();
+    let options = match Options::from_args(&args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("invalid arguments: {}", e);
+            exit(1);
+        },
+    };
+    if options.policy.is_none() && !options.report {
+        let file = File::open(&path).expect("no content");
+        let (total, valid) = Validator::new()
+            .count_valid(BufReader::new(file), &[Rule::Duplicates, Rule::Anagrams])
+            .expect("no content");
+        println!("{}/{} passphrase are valid under the old policy", valid[0], total);
+        println!("{}/{} passphrase are valid under the new policy", valid[1], total);
+        return;
+    }
+
     let contents = read(&path).expect("no content");
 
-    let mut valid = 0;
-    for passphrase in &contents {
-        if check_duplicates(&passphrase) {
-            valid += 1;
+    if options.report {
+        // the new policy catches the duplicates too
+        let policy = options.policy.unwrap_or_else(|| Box::new(NoAnagrams));
+        for (idx, passphrase) in contents.iter().enumerate() {
+            let violations = policy.violations(passphrase);
+            if !violations.is_empty() {
+                println!("{}", format_report(idx + 1, passphrase, &violations));
+            }
         }
+        return;
     }
-    println!("{}/{} passphrase are valid under the old policy", valid, contents.len());
 
-    valid = 0;
-    for passphrase in &contents {
-        if check_anagrams(&passphrase) {
-            valid += 1;
-        }
+    if let Some(custom) = options.policy {
+        let valid = count_valid(custom.as_ref(), &contents);
+        println!("{}/{} passphrase are valid under {}", valid, contents.len(), custom.name());
     }
-    println!("{}/{} passphrase are valid under the new policy", valid, contents.len());
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::ops::RangeInclusive;
     use rstest::rstest;
 
-    #[rstest(passphrase,
-    case("aa bb cc dd ee".to_string()),
-    case("aa bb cc dd aaa".to_string()),
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[rstest(args, valid,
+    case(&["--policy", "no-duplicates"], 2),
+    case(&["--policy", "no-anagrams"], 1),
+    case(&["--policy", "no-anagrams | min-words(5)"], 2),
     )]
-    fn test_no_duplicates(passphrase: String) {
-        assert!(check_duplicates(&passphrase));
+    fn test_count_valid(args: &[&str], valid: usize) {
+        let contents = to_args(&["aa bb cc dd ee", "aa bb cc dd aa", "abcde xyz ecdab"]);
+        let policy = Options::from_args(&to_args(args)).unwrap().policy.unwrap();
+        assert_eq!(count_valid(policy.as_ref(), &contents), valid);
     }
 
-    #[rstest(passphrase,
-    case("aa bb cc dd aa".to_string()),
+    #[rstest(args,
+    case(&["--policy"]),
+    case(&["--policy", "no-anagram"]),
+    case(&["--policy-file", "day_04/does_not_exist.txt"]),
+    case(&["--verbose"]),
     )]
-    fn test_has_duplicates(passphrase: String) {
-        assert!(!check_duplicates(&passphrase));
+    fn test_options_error(args: &[&str]) {
+        assert!(Options::from_args(&to_args(args)).is_err());
+    }
+
+    #[test]
+    fn test_options() {
+        let options = Options::from_args(&to_args(&["--report"])).unwrap();
+        assert!(options.report);
+        assert!(options.policy.is_none());
+        let options = Options::from_args(&to_args(&["--policy", "no-duplicates", "--report"])).unwrap();
+        assert!(options.report);
+        assert_eq!(options.policy.unwrap().name(), "no-duplicates");
     }
 
-    #[rstest(passphrase,
-    case("abcde fghij".to_string()),
-    case("a ab abc abd abf abj".to_string()),
-    case("iiii oiii ooii oooi oooo".to_string()),
+    #[rstest(args, kind, words,
+    case(&["--generate", "5"], Kind::Valid, 5..=10),
+    case(&["--generate", "5", "--kind", "anagram", "--words", "3"], Kind::Anagram, 3..=3),
+    case(&["--generate", "5", "--kind", "duplicate", "--letters", "xyz", "--length", "1-2", "--seed", "3"],
+         Kind::Duplicate, 5..=10),
+    case(&["--generate", "5", "--wordlist", "day_04/input.txt", "--words", "2-4", "--policy", "no-duplicates"],
+         Kind::Valid, 2..=4),
     )]
-    fn test_no_anagrams(passphrase: String) {
-        assert!(check_anagrams(&passphrase));
+    fn test_generate_options(args: &[&str], kind: Kind, words: RangeInclusive<usize>) {
+        let mut options = GenerateOptions::from_args(&to_args(args)).unwrap();
+        assert_eq!(options.count, 5);
+        assert_eq!(options.kind, kind);
+        let phrase = options.generator.generate(kind).unwrap();
+        assert!(words.contains(&phrase.split_whitespace().count()), "{}", phrase);
     }
 
-    #[rstest(passphrase,
-    case("abcde xyz ecdab".to_string()),
-    case("oiii ioii iioi iiio".to_string()),
+    #[rstest(args,
+    case(&["--generate"]),
+    case(&["--generate", "many"]),
+    case(&["--generate", "5", "--kind", "palindrome"]),
+    case(&["--generate", "5", "--letters", ""]),
+    case(&["--generate", "5", "--length", "0-2"]),
+    case(&["--generate", "5", "--wordlist", "day_04/does_not_exist.txt"]),
     )]
-    fn test_has_anagrams(passphrase: String) {
-        assert!(!check_anagrams(&passphrase));
+    fn test_generate_options_error(args: &[&str]) {
+        assert!(GenerateOptions::from_args(&to_args(args)).is_err());
     }
-}
\ No newline at end of file
+
+    #[rstest(expr, phrase, expected,
+    case("no-anagrams", "abcde xyz  ecdab",
+         "line 3: [abcde] xyz [ecdab]\n  no-anagrams: 'abcde' (word 1, col 1) and 'ecdab' (word 3, col 12)"),
+    case("min-words(3) & no-palindromes", "aba bb",
+         "line 3: [aba] [bb]\n  min-words(3): 2 words\n  no-palindromes: 'aba' (word 1, col 1)\n  no-palindromes: 'bb' (word 2, col 5)"),
+    )]
+    fn test_format_report(expr: &str, phrase: &str, expected: &str) {
+        let policy = policy::parse(expr).unwrap();
+        assert_eq!(format_report(3, phrase, &policy.violations(phrase)), expected);
+    }
+}
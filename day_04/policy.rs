@@ -0,0 +1,723 @@
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::hash::Hash;
+use std::fs::File;
+use std::io::{BufReader, BufRead};
+use std::iter::FromIterator;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A rule a passphrase has to follow to be valid.
+pub trait PassphrasePolicy: fmt::Debug {
+    /// How the policy is written in a policy expression.
+    fn name(&self) -> String;
+    /// Everything that breaks the policy in the phrase, empty if the phrase is valid.
+    fn violations(&self, phrase: &str) -> Vec<Violation>;
+
+    fn is_valid(&self, phrase: &str) -> bool {
+        self.violations(phrase).is_empty()
+    }
+}
+
+/// Where a word is in its phrase, both counted from 0 and the column in chars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WordPosition {
+    pub index: usize,
+    pub column: usize,
+}
+
+/// The words that made a phrase invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Offending {
+    /// two words colliding, the first one and the one repeating it
+    Pair(WordPosition, WordPosition),
+    /// words breaking the policy on their own, none when it is the phrase as a whole
+    Words(Vec<WordPosition>),
+}
+
+impl Offending {
+    /// All the words, in the order of the phrase.
+    pub fn positions(&self) -> Vec<WordPosition> {
+        match self {
+            Offending::Pair(a, b) => vec!(*a, *b),
+            Offending::Words(words) => words.clone(),
+        }
+    }
+
+    // the same words at the positions they have in another phrase
+    fn moved_to(&self, words: &[WordPosition]) -> Offending {
+        match self {
+            Offending::Pair(a, b) => Offending::Pair(words[a.index], words[b.index]),
+            Offending::Words(offending) => Offending::Words(offending.iter().map(|w| words[w.index]).collect()),
+        }
+    }
+}
+
+/// What made a phrase invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// name of the policy that was broken
+    pub rule: String,
+    pub words: Offending,
+}
+
+impl Violation {
+    fn new(rule: &dyn PassphrasePolicy, words: Offending) -> Violation {
+        Violation{
+            rule: rule.name(),
+            words,
+        }
+    }
+}
+
+/// The words of a phrase with the column (in chars) they start at.
+pub fn split_words(phrase: &str) -> Vec<(usize, &str)> {
+    let mut rv = Vec::new();
+    let mut start = None;
+    for (column, (idx, c)) in phrase.char_indices().enumerate() {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some((column, idx)),
+            (Some((start_column, start_idx)), true) => {
+                rv.push((start_column, &phrase[start_idx..idx]));
+                start = None;
+            },
+            _ => {},
+        }
+    }
+    if let Some((start_column, start_idx)) = start {
+        rv.push((start_column, &phrase[start_idx..]));
+    }
+    rv
+}
+
+/// The position of every word of a phrase.
+pub fn word_positions(phrase: &str) -> Vec<WordPosition> {
+    split_words(phrase).into_iter().enumerate()
+        .map(|(index, (column, _))| WordPosition{index, column})
+        .collect()
+}
+
+fn check_duplicates(phrase: &str) -> bool {
+    let mut hash = HashSet::new();
+
+    for word in phrase.split_whitespace() {
+        if hash.contains(word) {
+            return false;
+        }
+        hash.insert(word);
+    }
+
+    true
+}
+
+/// Unicode full case folding of the text, normalized so that it can be compared.
+/// Unlike lower casing, "ß" and "SS" both become "ss" and a final "ς" becomes "σ".
+pub fn fold_case(text: &str) -> String {
+    let nfc: String = text.nfc().collect();
+    caseless::default_case_fold_str(&nfc).nfc().collect()
+}
+
+/// What is left of a word once the order of its letters is ignored.
+///
+/// Letters are the grapheme clusters of the NFC form of the word, so "é" is one letter
+/// whether it was written with a combining accent or not.
+pub fn anagram_key(word: &str, ignore_case: bool) -> Vec<String> {
+    let word: String = if ignore_case {
+        fold_case(word)
+    } else {
+        word.nfc().collect()
+    };
+    let mut letters: Vec<String> = word.graphemes(true).map(|g| g.to_string()).collect();
+    letters.sort_unstable();
+    letters
+}
+
+fn check_anagrams(phrase: &str) -> bool {
+    let mut hash = HashSet::new();
+
+    for word in phrase.split_whitespace() {
+        let s = anagram_key(word, false);
+        if hash.contains(&s) {
+            return false;
+        }
+        hash.insert(s);
+    }
+
+    true
+}
+
+// pairs every word with the first one that had the same key
+fn collisions<K, F>(phrase: &str, key: F) -> Vec<(usize, usize)>
+    where K: Eq + Hash, F: Fn(&str) -> K
+{
+    let mut first: HashMap<K, usize> = HashMap::new();
+    let mut rv = Vec::new();
+
+    for (idx, word) in phrase.split_whitespace().enumerate() {
+        match first.entry(key(word)) {
+            Entry::Occupied(e) => rv.push((*e.get(), idx)),
+            Entry::Vacant(e) => {
+                e.insert(idx);
+            },
+        }
+    }
+    rv
+}
+
+/// The same word cannot appear twice (the old policy).
+#[derive(Debug)]
+pub struct NoDuplicates;
+
+impl PassphrasePolicy for NoDuplicates {
+    fn name(&self) -> String {
+        "no-duplicates".to_string()
+    }
+
+    fn violations(&self, phrase: &str) -> Vec<Violation> {
+        let words = word_positions(phrase);
+        collisions(phrase, |word| word.to_string()).into_iter()
+            .map(|(a, b)| Violation::new(self, Offending::Pair(words[a], words[b])))
+            .collect()
+    }
+
+    fn is_valid(&self, phrase: &str) -> bool {
+        check_duplicates(phrase)
+    }
+}
+
+/// No word can be an anagram of another one (the new policy).
+#[derive(Debug)]
+pub struct NoAnagrams;
+
+impl PassphrasePolicy for NoAnagrams {
+    fn name(&self) -> String {
+        "no-anagrams".to_string()
+    }
+
+    fn violations(&self, phrase: &str) -> Vec<Violation> {
+        let words = word_positions(phrase);
+        collisions(phrase, |word| anagram_key(word, false)).into_iter()
+            .map(|(a, b)| Violation::new(self, Offending::Pair(words[a], words[b])))
+            .collect()
+    }
+
+    fn is_valid(&self, phrase: &str) -> bool {
+        check_anagrams(phrase)
+    }
+}
+
+/// Words that read the same backward are not allowed.
+/// A single letter is not considered a palindrome.
+#[derive(Debug)]
+pub struct NoPalindromes;
+
+impl PassphrasePolicy for NoPalindromes {
+    fn name(&self) -> String {
+        "no-palindromes".to_string()
+    }
+
+    fn violations(&self, phrase: &str) -> Vec<Violation> {
+        word_positions(phrase).into_iter().zip(phrase.split_whitespace())
+            .filter(|(_, word)| word.chars().count() >= 2 && word.chars().eq(word.chars().rev()))
+            .map(|(position, _)| Violation::new(self, Offending::Words(vec!(position))))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct MinWords(pub usize);
+
+impl PassphrasePolicy for MinWords {
+    fn name(&self) -> String {
+        format!("min-words({})", self.0)
+    }
+
+    fn violations(&self, phrase: &str) -> Vec<Violation> {
+        if phrase.split_whitespace().count() < self.0 {
+            vec!(Violation::new(self, Offending::Words(Vec::new())))
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MaxWords(pub usize);
+
+impl PassphrasePolicy for MaxWords {
+    fn name(&self) -> String {
+        format!("max-words({})", self.0)
+    }
+
+    fn violations(&self, phrase: &str) -> Vec<Violation> {
+        // the words that should not be there
+        let extra: Vec<WordPosition> = word_positions(phrase).into_iter().skip(self.0).collect();
+        if extra.is_empty() {
+            Vec::new()
+        } else {
+            vec!(Violation::new(self, Offending::Words(extra)))
+        }
+    }
+}
+
+/// Only the given letters can be used in words.
+#[derive(Debug)]
+pub struct Alphabet(pub HashSet<char>);
+
+impl PassphrasePolicy for Alphabet {
+    fn name(&self) -> String {
+        let mut letters: Vec<char> = self.0.iter().cloned().collect();
+        letters.sort_unstable();
+        format!("alphabet({})", String::from_iter(letters))
+    }
+
+    fn violations(&self, phrase: &str) -> Vec<Violation> {
+        word_positions(phrase).into_iter().zip(phrase.split_whitespace())
+            .filter(|(_, word)| !word.chars().all(|c| self.0.contains(&c)))
+            .map(|(position, _)| Violation::new(self, Offending::Words(vec!(position))))
+            .collect()
+    }
+}
+
+/// Run the inner policy on the phrase with its case folded.
+#[derive(Debug)]
+pub struct IgnoreCase(pub Box<dyn PassphrasePolicy>);
+
+impl PassphrasePolicy for IgnoreCase {
+    fn name(&self) -> String {
+        format!("ignore-case({})", self.0.name())
+    }
+
+    fn violations(&self, phrase: &str) -> Vec<Violation> {
+        // folding can change the length of words, the columns are the ones of the phrase given
+        let words = word_positions(phrase);
+        self.0.violations(&fold_case(phrase)).into_iter()
+            .map(|v| Violation{
+                rule: format!("ignore-case({})", v.rule),
+                words: v.words.moved_to(&words),
+            })
+            .collect()
+    }
+}
+
+/// Valid when all the policies are.
+#[derive(Debug)]
+pub struct All(pub Vec<Box<dyn PassphrasePolicy>>);
+
+impl PassphrasePolicy for All {
+    fn name(&self) -> String {
+        let names: Vec<String> = self.0.iter().map(|p| p.name()).collect();
+        names.join(" & ")
+    }
+
+    fn violations(&self, phrase: &str) -> Vec<Violation> {
+        self.0.iter().flat_map(|p| p.violations(phrase)).collect()
+    }
+
+    fn is_valid(&self, phrase: &str) -> bool {
+        self.0.iter().all(|p| p.is_valid(phrase))
+    }
+}
+
+/// Valid when at least one of the policies is.
+#[derive(Debug)]
+pub struct Any(pub Vec<Box<dyn PassphrasePolicy>>);
+
+impl PassphrasePolicy for Any {
+    fn name(&self) -> String {
+        let names: Vec<String> = self.0.iter().map(|p| p.name()).collect();
+        format!("({})", names.join(" | "))
+    }
+
+    // all the violations if none of the policies is followed.
+    fn violations(&self, phrase: &str) -> Vec<Violation> {
+        let mut rv = Vec::new();
+        for p in &self.0 {
+            let violations = p.violations(phrase);
+            if violations.is_empty() {
+                return violations;
+            }
+            rv.extend(violations);
+        }
+        rv
+    }
+
+    fn is_valid(&self, phrase: &str) -> bool {
+        self.0.iter().any(|p| p.is_valid(phrase))
+    }
+}
+
+/// Parse a policy expression like `no-duplicates & (max-words(10) | ignore-case(no-anagrams))`.
+///
+/// Known policies are `no-duplicates`, `no-anagrams`, `no-palindromes`, `min-words(N)`,
+/// `max-words(N)`, `alphabet(LETTERS)` (where `a-z` is a range) and `ignore-case(EXPR)`.
+/// `&` binds tighter than `|`.
+pub fn parse(expr: &str) -> Result<Box<dyn PassphrasePolicy>, String> {
+    let mut parser = Parser{
+        chars: expr.chars().collect(),
+        pos: 0,
+    };
+    let rv = parser.expr()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("end of the policy"));
+    }
+    Ok(rv)
+}
+
+/// Read a policy file: one expression per line, all of them have to be valid.
+/// Blank lines and anything after a `#` are ignored.
+pub fn from_file(path: &str) -> Result<Box<dyn PassphrasePolicy>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let br = BufReader::new(file);
+    let mut policies = Vec::new();
+
+    for (idx, line) in br.lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", path, e))?;
+        let expr = line.split('#').next().unwrap_or("").trim();
+        if expr.is_empty() {
+            continue;
+        }
+        policies.push(parse(expr).map_err(|e| format!("{}:{}: {}", path, idx + 1, e))?);
+    }
+
+    if policies.is_empty() {
+        return Err(format!("{}: no policy found", path));
+    }
+    Ok(Box::new(All(policies)))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error(&self, expected: &str) -> String {
+        match self.chars.get(self.pos) {
+            Some(c) => format!("expected {} at {} but found '{}'", expected, self.pos, c),
+            None => format!("expected {} at {} but the policy ended", expected, self.pos),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn consume(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.consume(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("'{}'", expected)))
+        }
+    }
+
+    // expr := term ('|' term)*
+    fn expr(&mut self) -> Result<Box<dyn PassphrasePolicy>, String> {
+        let mut terms = vec!(self.term()?);
+        while self.consume('|') {
+            terms.push(self.term()?);
+        }
+        Ok(if terms.len() == 1 { terms.pop().unwrap() } else { Box::new(Any(terms)) })
+    }
+
+    // term := factor ('&' factor)*
+    fn term(&mut self) -> Result<Box<dyn PassphrasePolicy>, String> {
+        let mut factors = vec!(self.factor()?);
+        while self.consume('&') {
+            factors.push(self.factor()?);
+        }
+        Ok(if factors.len() == 1 { factors.pop().unwrap() } else { Box::new(All(factors)) })
+    }
+
+    // factor := '(' expr ')' | name | name '(' argument ')'
+    fn factor(&mut self) -> Result<Box<dyn PassphrasePolicy>, String> {
+        if self.consume('(') {
+            let rv = self.expr()?;
+            self.expect(')')?;
+            return Ok(rv);
+        }
+
+        self.skip_whitespace();
+        let start = self.pos;
+        let name = self.name();
+        let rv: Box<dyn PassphrasePolicy> = match name.as_str() {
+            "no-duplicates" => Box::new(NoDuplicates),
+            "no-anagrams" => Box::new(NoAnagrams),
+            "no-palindromes" => Box::new(NoPalindromes),
+            "min-words" => Box::new(MinWords(self.number_argument()?)),
+            "max-words" => Box::new(MaxWords(self.number_argument()?)),
+            "alphabet" => Box::new(Alphabet(self.alphabet_argument()?)),
+            "ignore-case" => {
+                self.expect('(')?;
+                let inner = self.expr()?;
+                self.expect(')')?;
+                Box::new(IgnoreCase(inner))
+            },
+            "" => return Err(self.error("a policy")),
+            _ => return Err(format!("unknown policy '{}' at {}", name, start)),
+        };
+        Ok(rv)
+    }
+
+    fn name(&mut self) -> String {
+        self.skip_whitespace();
+        let mut rv = String::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            if !(c.is_ascii_alphanumeric() || c == '-') {
+                break;
+            }
+            rv.push(c);
+            self.pos += 1;
+        }
+        rv
+    }
+
+    fn number_argument(&mut self) -> Result<usize, String> {
+        self.expect('(')?;
+        let value = self.name();
+        let rv = value.parse().map_err(|_| self.error("a number"))?;
+        self.expect(')')?;
+        Ok(rv)
+    }
+
+    // anything until the closing parenthesis
+    fn alphabet_argument(&mut self) -> Result<HashSet<char>, String> {
+        self.expect('(')?;
+        let mut letters = String::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            if c == ')' {
+                break;
+            }
+            letters.push(c);
+            self.pos += 1;
+        }
+        self.expect(')')?;
+        Ok(expand_letters(&letters).into_iter().collect())
+    }
+}
+
+/// The letters of `letters` where `a-z` is expanded to the whole range.
+pub fn expand_letters(letters: &str) -> Vec<char> {
+    let letters: Vec<char> = letters.chars().collect();
+    let mut rv = Vec::new();
+    let mut i = 0;
+    while i < letters.len() {
+        if i + 2 < letters.len() && letters[i + 1] == '-' {
+            rv.extend(letters[i]..=letters[i + 2]);
+            i += 3;
+        } else {
+            rv.push(letters[i]);
+            i += 1;
+        }
+    }
+    rv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(passphrase,
+    case("aa bb cc dd ee".to_string()),
+    case("aa bb cc dd aaa".to_string()),
+    )]
+    fn test_no_duplicates(passphrase: String) {
+        assert!(check_duplicates(&passphrase));
+    }
+
+    #[rstest(passphrase,
+    case("aa bb cc dd aa".to_string()),
+    )]
+    fn test_has_duplicates(passphrase: String) {
+        assert!(!check_duplicates(&passphrase));
+    }
+
+    #[rstest(passphrase,
+    case("abcde fghij".to_string()),
+    case("a ab abc abd abf abj".to_string()),
+    case("iiii oiii ooii oooi oooo".to_string()),
+    )]
+    fn test_no_anagrams(passphrase: String) {
+        assert!(check_anagrams(&passphrase));
+    }
+
+    #[rstest(passphrase,
+    case("abcde xyz ecdab".to_string()),
+    case("oiii ioii iioi iiio".to_string()),
+    )]
+    fn test_has_anagrams(passphrase: String) {
+        assert!(!check_anagrams(&passphrase));
+    }
+
+    #[rstest(word, ignore_case, expected,
+    case("abc", false, vec!("a", "b", "c")),
+    case("cAb", false, vec!("A", "b", "c")),
+    case("cAb", true, vec!("a", "b", "c")),
+    case("e\u{301}t\u{e9}", false, vec!("t", "\u{e9}", "\u{e9}")),  // both are composed
+    case("\u{c9}t\u{e9}", true, vec!("t", "\u{e9}", "\u{e9}")),
+    case("Stra\u{df}e", true, vec!("a", "e", "r", "s", "s", "s", "t")),  // ß folds to two letters
+    case("a\u{301}\u{328}b", false, vec!("b", "\u{105}\u{301}")),  // the accents stay on their letter
+    case("\u{1f1eb}\u{1f1f7}x", false, vec!("x", "\u{1f1eb}\u{1f1f7}")),  // a flag is one letter
+    )]
+    fn test_anagram_key(word: &str, ignore_case: bool, expected: Vec<&str>) {
+        assert_eq!(anagram_key(word, ignore_case), expected);
+    }
+
+    #[rstest(passphrase, expected,
+    case("caf\u{e9} face\u{301}", false),  // é composed or not
+    case("\u{e9}a a\u{e9} xyz", false),
+    case("e\u{301}a a\u{301}e", true),  // same chars but the accent is on another letter
+    case("\u{c9}t\u{e9} \u{e9}t\u{e9}", true),  // case matters
+    case("\u{1f1eb}\u{1f1f7} \u{1f1f7}\u{1f1eb}", true),  // France and Réunion
+    case("\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}x x\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}", false),
+    case("\u{1100}\u{1161} \u{ac00}", false),  // hangul jamo compose to the syllable
+    case("\u{3b1}\u{3b2}\u{3b3} \u{3b3}\u{3b2}\u{3b1}", false),
+    case("\u{3b1}\u{3b2}\u{3b3} \u{3b3}\u{3b2}\u{3b4}", true),
+    )]
+    fn test_anagrams_unicode(passphrase: &str, expected: bool) {
+        assert_eq!(check_anagrams(passphrase), expected);
+        assert_eq!(NoAnagrams.violations(passphrase).is_empty(), expected);
+    }
+
+    #[rstest(passphrase, expected,
+    case("\u{c9}t\u{e9} \u{e9}t\u{e9}", false),
+    case("\u{c9}te\u{301} t\u{e9}\u{e9}", false),
+    case("Stra\u{df}e STRASSE", false),  // ß folds to ss
+    case("Stra\u{df}e STRASE", true),
+    case("\u{3a3}\u{391} \u{3b1}\u{3c3}", false),
+    case("\u{3c3}\u{3bf}\u{3c6}\u{3bf}\u{3c2} \u{3a3}\u{39f}\u{3a6}\u{39f}\u{3a3}", false),  // final sigma
+    case("\u{3c3}\u{3bf}\u{3c6}\u{3bf}\u{3c2} \u{3c3}\u{3bf}\u{3c6}\u{3bf}\u{3c5}", true),
+    )]
+    fn test_anagrams_unicode_ignore_case(passphrase: &str, expected: bool) {
+        let policy = IgnoreCase(Box::new(NoAnagrams));
+        assert_eq!(policy.is_valid(passphrase), expected);
+    }
+
+    #[rstest(expr, passphrase, expected,
+    case("no-duplicates", "aa bb cc dd aa", false),
+    case("no-anagrams", "abcde xyz ecdab", false),
+    case("no-palindromes", "abc a def", true),
+    case("no-palindromes", "abc aba def", false),
+    case("min-words(3)", "aa bb", false),
+    case("min-words(3)", "aa bb cc", true),
+    case("max-words(2)", "aa bb cc", false),
+    case("alphabet(a-c)", "abc cab", true),
+    case("alphabet(a-cx)", "abc xyz", false),
+    case("no-duplicates", "aa AA", true),
+    case("ignore-case(no-duplicates)", "aa AA", false),
+    case("ignore-case(no-anagrams)", "Ab bA", false),
+    case("no-duplicates & max-words(2)", "aa bb", true),
+    case("no-duplicates & max-words(2)", "aa bb cc", false),
+    case("no-duplicates&max-words(2)|min-words(3)", "aa bb cc", true),
+    case("no-duplicates & (max-words(2) | min-words(3))", "aa aa", false),
+    case("  no-anagrams  |  no-duplicates ", "ab ba", true),
+    )]
+    fn test_parse(expr: &str, passphrase: &str, expected: bool) {
+        let policy = parse(expr).unwrap();
+        assert_eq!(policy.is_valid(passphrase), expected, "{:?}", policy);
+    }
+
+    #[rstest(expr, error,
+    case("", "expected a policy at 0 but the policy ended"),
+    case("no-dupes", "unknown policy 'no-dupes' at 0"),
+    case("no-anagrams &", "expected a policy at 13 but the policy ended"),
+    case("(no-anagrams", "expected ')' at 12 but the policy ended"),
+    case("max-words(two)", "expected a number at 13 but found ')'"),
+    case("no-anagrams no-duplicates", "expected end of the policy at 12 but found 'n'"),
+    )]
+    fn test_parse_error(expr: &str, error: &str) {
+        assert_eq!(parse(expr).unwrap_err(), error);
+    }
+
+    #[rstest(passphrase, expected,
+    case("abc def", true),
+    case("abc def Cba", false),  // ignore-case(no-anagrams)
+    case("abc", false),  // min-words(2)
+    case("abc dEf", false),  // alphabet(a-z)
+    case("abc aba def", true),  // max-words(3)
+    case("abc aba def ghi", false),  // no-palindromes
+    )]
+    fn test_from_file(passphrase: &str, expected: bool) {
+        let policy = from_file("day_04/policy.txt").unwrap();
+        assert_eq!(policy.is_valid(passphrase), expected);
+        assert_eq!(policy.violations(passphrase).is_empty(), expected);
+    }
+
+    #[rstest(expr, name,
+    case("no-duplicates", "no-duplicates"),
+    case("alphabet(c-a)", "alphabet()"),
+    case("alphabet(ca-b)", "alphabet(abc)"),
+    case("no-anagrams&(min-words(2)|ignore-case(no-palindromes))",
+         "no-anagrams & (min-words(2) | ignore-case(no-palindromes))"),
+    )]
+    fn test_name(expr: &str, name: &str) {
+        let policy = parse(expr).unwrap();
+        assert_eq!(policy.name(), name);
+        // the name can be parsed back
+        assert_eq!(parse(&policy.name()).unwrap().name(), name);
+    }
+
+    fn pair(rule: &str, a: (usize, usize), b: (usize, usize)) -> Violation {
+        let position = |(index, column)| WordPosition{index, column};
+        Violation{rule: rule.to_string(), words: Offending::Pair(position(a), position(b))}
+    }
+
+    fn words(rule: &str, words: &[(usize, usize)]) -> Violation {
+        let words = words.iter().map(|&(index, column)| WordPosition{index, column}).collect();
+        Violation{rule: rule.to_string(), words: Offending::Words(words)}
+    }
+
+    #[rstest(expr, passphrase, expected,
+    case("no-duplicates", "aa bb cc", vec!()),
+    case("no-duplicates", "aa bb aa cc aa bb",
+         vec!(pair("no-duplicates", (0, 0), (2, 6)),
+              pair("no-duplicates", (0, 0), (4, 12)),
+              pair("no-duplicates", (1, 3), (5, 15)))),
+    case("no-anagrams", "abcde xyz  ecdab", vec!(pair("no-anagrams", (0, 0), (2, 11)))),
+    case("ignore-case(no-anagrams)", "Abc cbA", vec!(pair("ignore-case(no-anagrams)", (0, 0), (1, 4)))),
+    // lower case \u{130} is two chars, the columns are still the ones of the phrase
+    case("ignore-case(no-duplicates)", "\u{130}a b \u{130}A",
+         vec!(pair("ignore-case(no-duplicates)", (0, 0), (2, 5)))),
+    case("no-palindromes", "a abba bob cd", vec!(words("no-palindromes", &[(1, 2)]),
+                                                 words("no-palindromes", &[(2, 7)]))),
+    case("min-words(3)", "aa bb", vec!(words("min-words(3)", &[]))),
+    case("max-words(1)", "aa bb cc", vec!(words("max-words(1)", &[(1, 3), (2, 6)]))),
+    case("alphabet(a-c)", "ab xy ca z", vec!(words("alphabet(abc)", &[(1, 3)]),
+                                             words("alphabet(abc)", &[(3, 9)]))),
+    case("max-words(1) & no-duplicates", "aa aa", vec!(words("max-words(1)", &[(1, 3)]),
+                                                       pair("no-duplicates", (0, 0), (1, 3)))),
+    case("max-words(1) | no-duplicates", "aa bb", vec!()),
+    case("max-words(1) | no-duplicates", "aa aa", vec!(words("max-words(1)", &[(1, 3)]),
+                                                       pair("no-duplicates", (0, 0), (1, 3)))),
+    )]
+    fn test_violations(expr: &str, passphrase: &str, expected: Vec<Violation>) {
+        let policy = parse(expr).unwrap();
+        assert_eq!(policy.violations(passphrase), expected);
+        assert_eq!(policy.is_valid(passphrase), expected.is_empty());
+    }
+
+    #[rstest(phrase, expected,
+    case("", vec!()),
+    case("aa bb", vec!((0, "aa"), (3, "bb"))),
+    case("  aa\tbb  ", vec!((2, "aa"), (5, "bb"))),
+    case("é ab", vec!((0, "é"), (2, "ab"))),  // columns are in chars
+    )]
+    fn test_split_words(phrase: &str, expected: Vec<(usize, &str)>) {
+        assert_eq!(split_words(phrase), expected);
+    }
+}
@@ -0,0 +1,63 @@
+use std::convert::{TryFrom, TryInto};
+use std::ops::RangeInclusive;
+
+/// Small seeded random generator (splitmix64) so that generated inputs can be reproduced.
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random{state: seed}
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// An index in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    pub fn between<T: Copy + TryInto<u64> + TryFrom<u64>>(&mut self, range: &RangeInclusive<T>) -> T {
+        let wide = |n: T| n.try_into().ok().expect("the range fits in a u64");
+        let start = wide(*range.start());
+        let rv = start + self.next() % (wide(*range.end()) - start + 1);
+        T::try_from(rv).ok().expect("picked in the range")
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_is_reproducible() {
+        let a: Vec<u64> = (0..10).scan(Random::new(4), |r, _| Some(r.next())).collect();
+        let b: Vec<u64> = (0..10).scan(Random::new(4), |r, _| Some(r.next())).collect();
+        let c: Vec<u64> = (0..10).scan(Random::new(5), |r, _| Some(r.next())).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_between() {
+        let mut random = Random::new(1);
+        for _ in 0..100 {
+            assert!((3..=5).contains(&random.between(&(3..=5usize))));
+            assert_eq!(random.between(&(7..=7u64)), 7);
+        }
+    }
+}
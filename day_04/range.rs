@@ -0,0 +1,35 @@
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// Parse a number like `3` or a range like `3-5` given on the command line.
+pub fn parse_range<T: FromStr + PartialOrd + Clone>(value: &str) -> Result<RangeInclusive<T>, String> {
+    let invalid = |_| format!("{} is not a number or a range like 3-5", value);
+    let (start, end) = match value.split_once('-') {
+        Some((start, end)) => (start.parse().map_err(invalid)?, end.parse().map_err(invalid)?),
+        None => {
+            let v: T = value.parse().map_err(invalid)?;
+            (v.clone(), v)
+        },
+    };
+    if start > end {
+        return Err(format!("{} is an empty range", value));
+    }
+    Ok(start..=end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(value, expected,
+    case("3", Ok(3..=3)),
+    case("3-5", Ok(3..=5)),
+    case("5-3", Err("5-3 is an empty range".to_string())),
+    case("3-", Err("3- is not a number or a range like 3-5".to_string())),
+    case("three", Err("three is not a number or a range like 3-5".to_string())),
+    )]
+    fn test_parse_range(value: &str, expected: Result<RangeInclusive<usize>, String>) {
+        assert_eq!(parse_range(value), expected);
+    }
+}
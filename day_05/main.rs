@@ -1,6 +1,15 @@
 use std::{io, env};
 use std::fs::File;
-use std::io::{BufReader, BufRead};
+use std::io::{BufReader, BufRead, BufWriter, Write};
+use std::process::exit;
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::{Add, RangeInclusive, Sub};
+use std::time::Instant;
+
+#[cfg(test)]
+#[path = "../day_04/random.rs"]
+mod random;
+mod wide;
 
 fn read(path: &str) -> io::Result<Vec<i32>> {
     let file = File::open(path)?;
@@ -14,7 +23,113 @@ fn read(path: &str) -> io::Result<Vec<i32>> {
     Ok(rv)
 }
 
-fn iteration(contents: &mut Vec<i32>, current: i32, ge: Option<i32>) -> Option<i32> {
+/// What offsets are stored as, `i64` for the `wide` executor which needs offsets that do not
+/// overflow.
+trait Offset: Copy + PartialOrd + Add<Output = Self> + Sub<Output = Self> + From<i32> {}
+
+impl<T: Copy + PartialOrd + Add<Output = T> + Sub<Output = T> + From<i32>> Offset for T {}
+
+/// How an offset changes after a jump.
+trait OffsetRule<T = i32> {
+    /// The new value of an offset that was just used to jump.
+    fn update(&self, offset: T) -> T;
+}
+
+/// Q1: every offset is incremented.
+struct Increment;
+
+impl<T: Offset> OffsetRule<T> for Increment {
+    fn update(&self, offset: T) -> T {
+        offset + T::from(1)
+    }
+}
+
+/// Q2: offsets of `threshold` or more are decremented, the others incremented.
+struct ThresholdDecrement(i32);
+
+impl<T: Offset> OffsetRule<T> for ThresholdDecrement {
+    fn update(&self, offset: T) -> T {
+        if offset >= T::from(self.0) {
+            offset - T::from(1)
+        } else {
+            offset + T::from(1)
+        }
+    }
+}
+
+impl<T, F: Fn(T) -> T> OffsetRule<T> for F {
+    fn update(&self, offset: T) -> T {
+        self(offset)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Condition {
+    Any,
+    Eq(i32),
+    Lt(i32),
+    Le(i32),
+    Gt(i32),
+    Ge(i32),
+}
+
+impl Condition {
+    fn matches<T: Offset>(&self, offset: T) -> bool {
+        match *self {
+            Condition::Any => true,
+            Condition::Eq(v) => offset == T::from(v),
+            Condition::Lt(v) => offset < T::from(v),
+            Condition::Le(v) => offset <= T::from(v),
+            Condition::Gt(v) => offset > T::from(v),
+            Condition::Ge(v) => offset >= T::from(v),
+        }
+    }
+}
+
+/// Adds the delta of the first matching condition, offsets matching nothing are not changed.
+#[derive(Debug, PartialEq, Eq)]
+struct TableRule(Vec<(Condition, i32)>);
+
+impl TableRule {
+    /// Parse rules like `>=3:-1,*:+1` (the Q2 rule).
+    /// Conditions are `*`, `=N`, `<N`, `<=N`, `>N` or `>=N`.
+    fn parse(value: &str) -> Result<TableRule, String> {
+        let mut rv = Vec::new();
+        for entry in value.split(',') {
+            let (condition, delta) = entry.trim().split_once(':')
+                .ok_or(format!("{} is not like CONDITION:DELTA", entry))?;
+            let number = |v: &str| v.trim().parse::<i32>().map_err(|_| format!("{} is not a number", v));
+            let condition = if condition.trim() == "*" {
+                Condition::Any
+            } else if let Some(v) = condition.strip_prefix("<=") {
+                Condition::Le(number(v)?)
+            } else if let Some(v) = condition.strip_prefix(">=") {
+                Condition::Ge(number(v)?)
+            } else if let Some(v) = condition.strip_prefix('<') {
+                Condition::Lt(number(v)?)
+            } else if let Some(v) = condition.strip_prefix('>') {
+                Condition::Gt(number(v)?)
+            } else if let Some(v) = condition.strip_prefix('=') {
+                Condition::Eq(number(v)?)
+            } else {
+                return Err(format!("unknown condition {}", condition));
+            };
+            rv.push((condition, number(delta)?));
+        }
+        Ok(TableRule(rv))
+    }
+}
+
+impl<T: Offset> OffsetRule<T> for TableRule {
+    fn update(&self, offset: T) -> T {
+        match self.0.iter().find(|(condition, _)| condition.matches(offset)) {
+            Some((_, delta)) => offset + T::from(*delta),
+            None => offset,
+        }
+    }
+}
+
+fn iteration<R: OffsetRule + ?Sized>(contents: &mut [i32], current: i32, rule: &R) -> Option<i32> {
     if current < 0 {
         return None;
     }
@@ -22,46 +137,501 @@ fn iteration(contents: &mut Vec<i32>, current: i32, ge: Option<i32>) -> Option<i
     let value = contents.get(idx)?;
     let next = current + value;  // to get the value before we change it.
 
-    let dec = if let Some(ge_v) = ge {
-        *value >= ge_v
-    } else {
-        false
-    };
-    if dec {
-        contents[idx] -= 1;
-    } else {
-        contents[idx] += 1;
-    }
+    contents[idx] = rule.update(*value);
 
     Some(next)
 }
 
-fn execute(contents: &[i32], ge: Option<i32>) -> u32 {
-    let mut i = 0;
-    let mut contents = contents.to_vec();
-    let mut current = 0;
+/// One jump through the maze.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TraceEntry {
+    step: u32,
+    pointer: i32,
+    before: i32,
+    after: i32,
+}
+
+/// Told about every jump, to see what is going on in a maze.
+trait Trace {
+    fn record(&mut self, entry: TraceEntry) -> io::Result<()>;
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps the last `capacity` jumps.
+struct RingTrace {
+    capacity: usize,
+    entries: VecDeque<TraceEntry>,
+}
+
+impl RingTrace {
+    fn new(capacity: usize) -> RingTrace {
+        RingTrace{
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+}
+
+impl Trace for RingTrace {
+    fn record(&mut self, entry: TraceEntry) -> io::Result<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+        Ok(())
+    }
+}
+
+/// Writes every jump as a CSV line.
+struct WriterTrace<W: Write> {
+    writer: W,
+}
+
+impl WriterTrace<BufWriter<File>> {
+    fn create(path: &str) -> io::Result<Self> {
+        WriterTrace::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> WriterTrace<W> {
+    fn new(mut writer: W) -> io::Result<Self> {
+        writeln!(writer, "step,pointer,before,after")?;
+        Ok(WriterTrace{writer})
+    }
+}
+
+impl<W: Write> Trace for WriterTrace<W> {
+    fn record(&mut self, entry: TraceEntry) -> io::Result<()> {
+        writeln!(self.writer, "{},{},{},{}", entry.step, entry.pointer, entry.before, entry.after)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Counts how many times each offset was jumped from.
+struct VisitTrace {
+    visits: Vec<u32>,
+}
+
+impl Trace for VisitTrace {
+    fn record(&mut self, entry: TraceEntry) -> io::Result<()> {
+        self.visits[entry.pointer as usize] += 1;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// out of the maze after that many steps
+    Escaped(u32),
+    /// still in the maze when the maximum number of steps was reached
+    DidNotTerminate(u32),
+}
+
+/// The list of offsets being jumped through.
+struct JumpMaze<'a, R: OffsetRule + ?Sized> {
+    offsets: Vec<i32>,
+    pointer: i32,
+    steps: u32,
+    rule: &'a R,
+}
+
+impl<'a, R: OffsetRule + ?Sized> JumpMaze<'a, R> {
+    fn new(offsets: &[i32], rule: &'a R) -> Self {
+        JumpMaze{
+            offsets: offsets.to_vec(),
+            pointer: 0,
+            steps: 0,
+            rule,
+        }
+    }
+
+    /// Jump once, false when we are out of the maze.
+    fn step(&mut self) -> bool {
+        match iteration(&mut self.offsets, self.pointer, self.rule) {
+            Some(next) => {
+                self.pointer = next;
+                self.steps += 1;
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn run(&mut self) -> u32 {
+        while self.step() {}
+        self.steps
+    }
+
+    /// Like `run` but gives up after `max_steps` and records every jump in `trace`.
+    fn run_with(&mut self, max_steps: Option<u32>, mut trace: Option<&mut dyn Trace>) -> io::Result<Outcome> {
+        let rv = loop {
+            let pointer = self.pointer;
+            let before = if pointer < 0 { None } else { self.offsets.get(pointer as usize) };
+            let before = match before {
+                Some(&before) => before,
+                None => break Outcome::Escaped(self.steps),
+            };
+            if max_steps.is_some_and(|max| self.steps >= max) {
+                break Outcome::DidNotTerminate(self.steps);
+            }
+            self.step();
+            if let Some(trace) = trace.as_mut() {
+                trace.record(TraceEntry{
+                    step: self.steps,
+                    pointer,
+                    before,
+                    after: self.offsets[pointer as usize],
+                })?;
+            }
+        };
+        if let Some(trace) = trace {
+            trace.finish()?;
+        }
+        Ok(rv)
+    }
+}
+
+/// What happens to a maze when it is run for long enough.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Classification {
+    /// out of the maze after that many steps
+    Escapes(u32),
+    /// the state after `start` steps comes back every `length` steps
+    Loops { start: u32, length: u32 },
+    /// no answer after that many steps
+    Unknown(u32),
+}
+
+impl<'a, R: OffsetRule + ?Sized> JumpMaze<'a, R> {
+    fn is_out(&self) -> bool {
+        self.pointer < 0 || self.pointer as usize >= self.offsets.len()
+    }
+
+    fn same_state(&self, other: &Self) -> bool {
+        self.pointer == other.pointer && self.offsets == other.offsets
+    }
+
+    fn snapshot(&self) -> Self {
+        JumpMaze{
+            offsets: self.offsets.clone(),
+            pointer: self.pointer,
+            steps: self.steps,
+            rule: self.rule,
+        }
+    }
+
+    /// Run the maze until it escapes or a (pointer, offsets) state repeats.
+    ///
+    /// Uses Brent's algorithm so only one copy of the offsets is kept. A maze that never escapes
+    /// always ends up repeating itself: every offset that is read keeps the pointer in the maze
+    /// so they are all bounded by its length.
+    fn classify(contents: &[i32], rule: &'a R, max_steps: Option<u32>) -> Classification {
+        let mut hare = JumpMaze::new(contents, rule);
+        let mut tortoise = hare.snapshot();
+        let mut power = 1;
+        let mut length = 0;
+
+        loop {
+            if hare.is_out() {
+                return Classification::Escapes(hare.steps);
+            }
+            if max_steps.is_some_and(|max| hare.steps >= max) {
+                return Classification::Unknown(hare.steps);
+            }
+            hare.step();
+            length += 1;
+            if hare.same_state(&tortoise) {
+                break;
+            }
+            if length == power {
+                tortoise = hare.snapshot();
+                power *= 2;
+                length = 0;
+            }
+        }
+
+        // start both again, `length` steps apart, they meet at the start of the loop
+        let mut tortoise = JumpMaze::new(contents, rule);
+        let mut hare = JumpMaze::new(contents, rule);
+        for _ in 0..length {
+            hare.step();
+        }
+        while !hare.same_state(&tortoise) {
+            tortoise.step();
+            hare.step();
+        }
+        Classification::Loops {start: tortoise.steps, length}
+    }
+}
+
+fn execute<R: OffsetRule + ?Sized>(contents: &[i32], rule: &R) -> u32 {
+    JumpMaze::new(contents, rule).run()
+}
+
+// from cold to hot, a cell that was never visited is blank
+const HEAT: &[u8] = b" .:-=+*#%@";
+
+/// What is left of a maze once it has been run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Analysis {
+    outcome: Outcome,
+    /// the offsets at the end of the run
+    offsets: Vec<i32>,
+    /// how many times each cell was jumped from
+    visits: Vec<u32>,
+}
+
+impl Analysis {
+    /// The `n` most visited cells as `(index, visits)`, most visited first.
+    fn hottest(&self, n: usize) -> Vec<(usize, u32)> {
+        let mut rv: Vec<(usize, u32)> = self.visits.iter().cloned().enumerate().collect();
+        rv.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        rv.truncate(n);
+        rv
+    }
+
+    /// How many cells end with each offset.
+    /// Offsets are grouped in ranges of the same width when there are more than `buckets` values.
+    fn histogram(&self, buckets: usize) -> Vec<(RangeInclusive<i32>, usize)> {
+        let mut counts = BTreeMap::new();
+        for &offset in &self.offsets {
+            *counts.entry(offset).or_insert(0) += 1;
+        }
+        if counts.len() <= buckets {
+            return counts.into_iter().map(|(offset, count)| (offset..=offset, count)).collect();
+        }
+
+        let min = i64::from(self.offsets.iter().cloned().min().unwrap());
+        let max = i64::from(self.offsets.iter().cloned().max().unwrap());
+        let width = (max - min + buckets as i64) / buckets as i64;
+        let mut rv: Vec<(RangeInclusive<i32>, usize)> = (0..buckets as i64)
+            .map(|i| min + i * width)
+            .take_while(|&start| start <= max)
+            .map(|start| (start as i32..=(start + width - 1).min(max) as i32, 0))
+            .collect();
+        for (offset, count) in counts {
+            rv[((i64::from(offset) - min) / width) as usize].1 += count;
+        }
+        rv
+    }
+
+    /// One character per cell, `width` cells per line, on a log scale so that the cold cells
+    /// do not all look the same next to the hottest ones.
+    fn heat_map(&self, width: usize) -> String {
+        let max = self.visits.iter().cloned().max().unwrap_or(0);
+        let top = HEAT.len() - 1;
+        let level = |visits: u32| match visits {
+            0 => 0,
+            _ if max == 1 => top,
+            _ => 1 + ((f64::from(visits).ln() / f64::from(max).ln()) * (top - 1) as f64) as usize,
+        };
+        self.visits
+            .chunks(width.max(1))
+            .map(|row| row.iter().map(|&v| HEAT[level(v)] as char).collect::<String>())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// Like `execute` but keeps the final offsets and counts the visits of each cell.
+fn analyse<R: OffsetRule + ?Sized>(contents: &[i32], rule: &R, max_steps: Option<u32>) -> io::Result<Analysis> {
+    let mut maze = JumpMaze::new(contents, rule);
+    let mut visits = VisitTrace{visits: vec![0; contents.len()]};
+    let outcome = maze.run_with(max_steps, Some(&mut visits))?;
+    Ok(Analysis{
+        outcome,
+        offsets: maze.offsets,
+        visits: visits.visits,
+    })
+}
+
+struct Options {
+    rule: Option<TableRule>,
+    max_steps: Option<u32>,
+    trace_path: Option<String>,
+    trace_last: Option<usize>,
+    classify: bool,
+    wide: bool,
+    bench: bool,
+    report: bool,
+}
+
+// usage: day_05 PATH [--rule TABLE] [--max-steps N] [--trace PATH | --trace-last N] [--classify] [--wide | --bench]
+//                    [--report]
+impl Options {
+    fn from_args(args: &[String]) -> Result<Options, String> {
+        let mut rv = Options{
+            rule: None,
+            max_steps: None,
+            trace_path: None,
+            trace_last: None,
+            classify: false,
+            wide: false,
+            bench: false,
+            report: false,
+        };
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--rule" => rv.rule = Some(TableRule::parse(value()?)?),
+                "--max-steps" => rv.max_steps = Some(value()?.parse().map_err(|_| "--max-steps needs a number")?),
+                "--trace" => rv.trace_path = Some(value()?.clone()),
+                "--trace-last" => rv.trace_last = Some(value()?.parse().map_err(|_| "--trace-last needs a number")?),
+                "--classify" => rv.classify = true,
+                "--wide" => rv.wide = true,
+                "--bench" => rv.bench = true,
+                "--report" => rv.report = true,
+                other => return Err(format!("unknown argument {}", other)),
+            }
+        }
+        if rv.trace_path.is_some() && rv.trace_last.is_some() {
+            return Err("--trace and --trace-last cannot be used together".to_string());
+        }
+        if rv.classify && (rv.trace_path.is_some() || rv.trace_last.is_some()) {
+            return Err("--classify cannot be traced".to_string());
+        }
+        if (rv.wide || rv.bench) && (rv.classify || rv.max_steps.is_some() || rv.trace_path.is_some() || rv.trace_last.is_some()) {
+            return Err("--wide and --bench only run the maze to the end".to_string());
+        }
+        if rv.report && (rv.classify || rv.wide || rv.bench || rv.trace_path.is_some() || rv.trace_last.is_some()) {
+            return Err("--report can only be used with --rule and --max-steps".to_string());
+        }
+        Ok(rv)
+    }
+}
+
+fn solve<R: OffsetRule + OffsetRule<i64> + ?Sized>(name: &str, contents: &[i32], rule: &R, options: &Options) -> io::Result<()> {
+    if options.bench {
+        let start = Instant::now();
+        let i = execute(contents, rule);
+        let slow = start.elapsed();
+        let start = Instant::now();
+        let rv = wide::escape(contents, rule);
+        let wide = start.elapsed();
+        assert_eq!(u64::from(i), rv.steps, "the executors disagree");
+        println!("{}: {} iterations, execute took {:?}, wide took {:?}", name, i, slow, wide);
+        return Ok(());
+    }
+    if options.wide {
+        let rv = wide::escape(contents, rule);
+        let exit = match rv.exit {
+            wide::Exit::Start => "the start",
+            wide::Exit::End => "the end",
+        };
+        println!("{}: left by {} after {} iterations, at {}", name, exit, rv.steps, rv.pointer);
+        return Ok(());
+    }
+    if options.report {
+        let analysis = analyse(contents, rule, options.max_steps)?;
+        print_report(name, &analysis);
+        return Ok(());
+    }
+    if options.classify {
+        match JumpMaze::classify(contents, rule, options.max_steps) {
+            Classification::Escapes(i) => println!("{}: escapes after {} iterations", name, i),
+            Classification::Loops { start, length } =>
+                println!("{}: loops from iteration {} every {} iterations", name, start, length),
+            Classification::Unknown(i) => println!("{}: neither escaped nor looped after {} iterations", name, i),
+        }
+        return Ok(());
+    }
+    if options.max_steps.is_none() && options.trace_path.is_none() && options.trace_last.is_none() {
+        let i = execute(contents, rule);
+        println!("{}: reached the end after {} iterations", name, i);
+        return Ok(());
+    }
+
+    let mut maze = JumpMaze::new(contents, rule);
+    let mut ring = options.trace_last.map(RingTrace::new);
+    let mut writer = match &options.trace_path {
+        // one file per question
+        Some(path) => Some(WriterTrace::create(&format!("{}.{}", path, name.to_lowercase()))?),
+        None => None,
+    };
+    let trace: Option<&mut dyn Trace> = match (&mut ring, &mut writer) {
+        (Some(ring), _) => Some(ring),
+        (_, Some(writer)) => Some(writer),
+        _ => None,
+    };
 
-    while let Some(res) = iteration(&mut contents, current, ge) {
-        i += 1;
-        current = res;
+    match maze.run_with(options.max_steps, trace)? {
+        Outcome::Escaped(i) => println!("{}: reached the end after {} iterations", name, i),
+        Outcome::DidNotTerminate(i) => println!("{}: still in the maze after {} iterations", name, i),
+    }
+    if let Some(ring) = ring {
+        for entry in ring.entries {
+            println!("  step {} at {}: {} -> {}", entry.step, entry.pointer, entry.before, entry.after);
+        }
     }
+    Ok(())
+}
 
-    i
+fn print_report(name: &str, analysis: &Analysis) {
+    match analysis.outcome {
+        Outcome::Escaped(i) => println!("{}: reached the end after {} iterations", name, i),
+        Outcome::DidNotTerminate(i) => println!("{}: still in the maze after {} iterations", name, i),
+    }
+    println!("  hottest cells:");
+    for (cell, visits) in analysis.hottest(10) {
+        println!("    {:>6}: {} visits, offset now {}", cell, visits, analysis.offsets[cell]);
+    }
+    println!("  final offsets:");
+    let histogram = analysis.histogram(20);
+    let most = histogram.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    for (range, count) in histogram {
+        let label = if range.start() == range.end() {
+            range.start().to_string()
+        } else {
+            format!("{}..={}", range.start(), range.end())
+        };
+        // bars of at most 50 characters
+        println!("    {:>14} {:>6} {}", label, count, "#".repeat((count * 50).div_ceil(most)));
+    }
+    println!("  heat map (log scale, 50 cells per line):");
+    for line in analysis.heat_map(50).lines() {
+        println!("    |{}|", line);
+    }
 }
 
 fn main() {
     let path = env::args().nth(1).expect("please supply a path");
+    let args: Vec<String> = env::args().skip(2).collect();
+    let options = match Options::from_args(&args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("invalid arguments: {}", e);
+            exit(1);
+        },
+    };
     let contents = read(&path).expect("no content");
-    let i = execute(&contents, None);
-    println!("Q1: reached the end after {} iterations", i);
-    let i = execute(&contents, Some(3));
-    println!("Q2: reached the end after {} iterations", i);
+
+    let rv = match &options.rule {
+        Some(rule) => solve("Rule", &contents, rule, &options),
+        None => solve("Q1", &contents, &Increment, &options)
+            .and_then(|_| solve("Q2", &contents, &ThresholdDecrement(3), &options)),
+    };
+    if let Err(e) = rv {
+        eprintln!("failed: {}", e);
+        exit(1);
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use rstest::rstest;
+    use std::collections::HashMap;
 
     #[rstest(input, current, exp_next, new_value,
     case(&[0, 3, 0, 1, -3], 0, Some(0), Some(1)),
@@ -73,7 +643,7 @@ mod test {
     )]
     fn test_iteration_q1(input: &[i32], current: i32, exp_next: Option<i32>, new_value: Option<i32>) {
         let mut contents = input.to_vec();
-        let rv = iteration(&mut contents, current, None);
+        let rv = iteration(&mut contents, current, &Increment);
         assert_eq!(rv, exp_next);
         if let Some(exp_value) = new_value {
             assert_eq!(contents[current as usize], exp_value);
@@ -84,13 +654,219 @@ mod test {
     case(&[0, 3, 0, 1, -3], 5),
     )]
     fn test_execute_q1(input: &[i32], exp_steps: u32) {
-        assert_eq!(execute(input, None), exp_steps);
+        assert_eq!(execute(input, &Increment), exp_steps);
     }
 
     #[rstest(input, exp_steps,
     case(&[0, 3, 0, 1, -3], 10),
     )]
     fn test_execute_q2(input: &[i32], exp_steps: u32) {
-        assert_eq!(execute(input, Some(3)), exp_steps);
+        assert_eq!(execute(input, &ThresholdDecrement(3)), exp_steps);
+    }
+
+    #[rstest(offset, expected,
+    case(-5, -4),
+    case(2, 3),
+    case(3, 2),
+    case(10, 9),
+    )]
+    fn test_threshold_decrement(offset: i32, expected: i32) {
+        assert_eq!(ThresholdDecrement(3).update(offset), expected);
+    }
+
+    #[test]
+    fn test_closure_rule() {
+        // jump twice as far next time
+        let rule = |offset: i32| offset * 2;
+        assert_eq!(rule.update(3), 6);
+        assert_eq!(execute(&[1, 1, -2, 1], &rule), 5);
+    }
+
+    #[rstest(value, expected,
+    case(">=3:-1,*:+1", TableRule(vec!((Condition::Ge(3), -1), (Condition::Any, 1)))),
+    case("<0: 2, =0:1, <=1:0, >5:-3", TableRule(vec!((Condition::Lt(0), 2), (Condition::Eq(0), 1),
+                                                        (Condition::Le(1), 0), (Condition::Gt(5), -3)))),
+    )]
+    fn test_table_rule_parse(value: &str, expected: TableRule) {
+        assert_eq!(TableRule::parse(value).unwrap(), expected);
+    }
+
+    #[rstest(value,
+    case(""),
+    case(">=3"),
+    case("~3:1"),
+    case(">=three:1"),
+    case("*:one"),
+    )]
+    fn test_table_rule_parse_error(value: &str) {
+        assert!(TableRule::parse(value).is_err());
+    }
+
+    #[rstest(value, offset, expected,
+    case("<0:2,*:1", -3, -1),
+    case("<0:2,*:1", 0, 1),
+    case("<0:2,=0:1", 4, 4),  // nothing matches
+    )]
+    fn test_table_rule(value: &str, offset: i32, expected: i32) {
+        assert_eq!(TableRule::parse(value).unwrap().update(offset), expected);
+    }
+
+    #[rstest(input, table, exp_steps,
+    case(&[0, 3, 0, 1, -3], "*:1", 5),
+    case(&[0, 3, 0, 1, -3], ">=3:-1,*:1", 10),
+    )]
+    fn test_execute_table(input: &[i32], table: &str, exp_steps: u32) {
+        // the built-in rules as tables
+        assert_eq!(execute(input, &TableRule::parse(table).unwrap()), exp_steps);
+    }
+
+    #[rstest(input, table, max_steps, expected,
+    case(&[0, 3, 0, 1, -3], "*:1", None, Outcome::Escaped(5)),
+    case(&[0, 3, 0, 1, -3], "*:1", Some(5), Outcome::Escaped(5)),
+    case(&[0, 3, 0, 1, -3], "*:1", Some(4), Outcome::DidNotTerminate(4)),
+    case(&[0], "=0:0", Some(1000), Outcome::DidNotTerminate(1000)),  // never moves
+    case(&[1, -1], "*:0", Some(10), Outcome::DidNotTerminate(10)),  // back and forth
+    case(&[], "*:1", Some(0), Outcome::Escaped(0)),
+    case(&[2], "*:1", Some(0), Outcome::DidNotTerminate(0)),
+    case(&[2], "*:1", Some(1), Outcome::Escaped(1)),
+    )]
+    fn test_run_with_max_steps(input: &[i32], table: &str, max_steps: Option<u32>, expected: Outcome) {
+        let rule = TableRule::parse(table).unwrap();
+        let mut maze = JumpMaze::new(input, &rule);
+        assert_eq!(maze.run_with(max_steps, None).unwrap(), expected);
+    }
+
+    fn entry(step: u32, pointer: i32, before: i32, after: i32) -> TraceEntry {
+        TraceEntry{step, pointer, before, after}
+    }
+
+    #[rstest(capacity, expected,
+    case(0, vec!()),
+    case(2, vec!(entry(4, 4, -3, -2), entry(5, 1, 4, 5))),
+    case(10, vec!(entry(1, 0, 0, 1), entry(2, 0, 1, 2), entry(3, 1, 3, 4),
+                  entry(4, 4, -3, -2), entry(5, 1, 4, 5))),
+    )]
+    fn test_ring_trace(capacity: usize, expected: Vec<TraceEntry>) {
+        let mut trace = RingTrace::new(capacity);
+        let mut maze = JumpMaze::new(&[0, 3, 0, 1, -3], &Increment);
+        maze.run_with(None, Some(&mut trace)).unwrap();
+        assert_eq!(trace.entries, expected);
+    }
+
+    #[test]
+    fn test_writer_trace() {
+        let mut trace = WriterTrace::new(Vec::new()).unwrap();
+        let rule = |offset: i32| offset;
+        let mut maze = JumpMaze::new(&[1, -1], &rule);
+        assert_eq!(maze.run_with(Some(3), Some(&mut trace)).unwrap(), Outcome::DidNotTerminate(3));
+        assert_eq!(
+            String::from_utf8(trace.writer).unwrap(),
+            "step,pointer,before,after\n1,0,1,1\n2,1,-1,-1\n3,0,1,1\n",
+        );
+    }
+
+    #[rstest(input, table, max_steps, expected,
+    case(&[0, 3, 0, 1, -3], "*:1", None, Classification::Escapes(5)),
+    case(&[0, 3, 0, 1, -3], ">=3:-1,*:1", None, Classification::Escapes(10)),
+    case(&[], "*:1", None, Classification::Escapes(0)),
+    case(&[0], "=0:0", None, Classification::Loops {start: 0, length: 1}),
+    case(&[1, -1], "*:0", None, Classification::Loops {start: 0, length: 2}),
+    case(&[1, 1, -1], "*:0", None, Classification::Loops {start: 1, length: 2}),
+    case(&[-2, 0, 5], "<0:1,*:0", None, Classification::Escapes(1)),
+    // the offsets flip between 0 and 1 until the first state comes back
+    case(&[1, 0, -2], "=0:1,=1:-1,*:0", None, Classification::Loops {start: 0, length: 5}),
+    case(&[1, 0, -2], "=0:1,=1:-1,*:0", Some(3), Classification::Unknown(3)),
+    )]
+    fn test_classify(input: &[i32], table: &str, max_steps: Option<u32>, expected: Classification) {
+        let rule = TableRule::parse(table).unwrap();
+        assert_eq!(JumpMaze::classify(input, &rule, max_steps), expected);
+    }
+
+    #[test]
+    fn test_classify_against_history() {
+        // compare with keeping every state on small mazes where offsets flip between -1, 0 and 1
+        let rule = TableRule::parse("=-1:1,=1:0,=0:-1").unwrap();
+        let mut loops = 0;
+        for code in 0..3u32.pow(5) {
+            let input: Vec<i32> = (0..5).map(|i| (code / 3u32.pow(i) % 3) as i32 - 1).collect();
+            let mut history = HashMap::new();
+            let mut maze = JumpMaze::new(&input, &rule);
+            let expected = loop {
+                if maze.is_out() {
+                    break Classification::Escapes(maze.steps);
+                }
+                if let Some(&start) = history.get(&(maze.pointer, maze.offsets.clone())) {
+                    break Classification::Loops {start, length: maze.steps - start};
+                }
+                history.insert((maze.pointer, maze.offsets.clone()), maze.steps);
+                maze.step();
+            };
+            assert_eq!(JumpMaze::classify(&input, &rule, None), expected, "{:?}", input);
+            if let Classification::Loops { .. } = expected {
+                loops += 1;
+            }
+        }
+        // some of them at least
+        assert!(loops > 0 && loops < 3u32.pow(5));
+    }
+
+    #[test]
+    fn test_analyse() {
+        let analysis = analyse(&[0, 3, 0, 1, -3], &Increment, None).unwrap();
+        assert_eq!(analysis, Analysis{
+            outcome: Outcome::Escaped(5),
+            offsets: vec!(2, 5, 0, 1, -2),
+            visits: vec!(2, 2, 0, 0, 1),
+        });
+        assert_eq!(analysis.hottest(2), vec!((0, 2), (1, 2)));
+        assert_eq!(analysis.hottest(10).len(), 5);
+        assert_eq!(analysis.heat_map(5), "@@  .");
+        assert_eq!(analysis.heat_map(2), "@@\n  \n.");
+
+        let analysis = analyse(&[0, 3, 0, 1, -3], &Increment, Some(2)).unwrap();
+        assert_eq!(analysis.outcome, Outcome::DidNotTerminate(2));
+        assert_eq!(analysis.visits, vec!(2, 0, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_analyse_input() {
+        let contents = read("day_05/input.txt").unwrap();
+        let analysis = analyse(&contents, &Increment, None).unwrap();
+        assert_eq!(analysis.visits.iter().sum::<u32>(), 318883);
+        assert_eq!(analysis.offsets.len(), contents.len());
+        let map = analysis.heat_map(50);
+        assert_eq!(map.lines().count(), contents.len().div_ceil(50));
+        assert!(map.contains('@'));
+    }
+
+    #[rstest(offsets, buckets, expected,
+    case(vec!(2, 5, 0, 1, -2), 10, vec!((-2..=-2, 1), (0..=0, 1), (1..=1, 1), (2..=2, 1), (5..=5, 1))),
+    case(vec!(2, 5, 0, 1, -2), 2, vec!((-2..=1, 3), (2..=5, 2))),
+    case(vec!(2, 5, 0, 1, -2), 3, vec!((-2..=0, 2), (1..=3, 2), (4..=5, 1))),
+    case(vec!(3, 3, 3, 2), 1, vec!((2..=3, 4))),
+    case(vec!(i32::MIN, i32::MAX), 1, vec!((i32::MIN..=i32::MAX, 2))),
+    )]
+    fn test_histogram(offsets: Vec<i32>, buckets: usize, expected: Vec<(RangeInclusive<i32>, usize)>) {
+        let analysis = Analysis{outcome: Outcome::Escaped(0), visits: vec!(0; offsets.len()), offsets};
+        assert_eq!(analysis.histogram(buckets), expected);
+    }
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[rstest(args,
+    case(&["--max-steps"]),
+    case(&["--max-steps", "many"]),
+    case(&["--trace-last", "-1"]),
+    case(&["--rule", "~3:1"]),
+    case(&["--trace", "trace.csv", "--trace-last", "3"]),
+    case(&["--classify", "--trace-last", "3"]),
+    case(&["--report", "--classify"]),
+    case(&["--report", "--trace", "trace.csv"]),
+    case(&["--verbose"]),
+    )]
+    fn test_options_error(args: &[&str]) {
+        assert!(Options::from_args(&to_args(args)).is_err());
     }
 }
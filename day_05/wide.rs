@@ -0,0 +1,205 @@
+use std::convert::TryFrom;
+
+use crate::OffsetRule;
+
+/// Which end of the maze the pointer left by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// jumped to a negative index
+    Start,
+    /// jumped past the last offset
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Escape {
+    pub steps: u64,
+    pub exit: Exit,
+    /// where the pointer landed outside of the maze
+    pub pointer: i64,
+}
+
+// settled cells are looked at this many at a time
+const WINDOW: usize = 12;
+
+// What running the pointer through a window of settled cells does, packed in a u32: the cells
+// it went through in the low 16 bits, where it lands counted from the start of the window in
+// the next 8 bits and the number of steps in the top 8.
+fn pass(cells: u32) -> u32 {
+    let mut visited = 0;
+    let mut exit = 0;
+    let mut steps = 0;
+    while exit < WINDOW {
+        visited |= 1 << exit;
+        exit += if cells & 1 << exit == 0 { 2 } else { 3 };
+        steps += 1;
+    }
+    visited | (exit as u32) << 16 | steps << 24
+}
+
+/// Run the maze until the pointer escapes, never returns if the maze loops.
+///
+/// Offsets are widened to `i64` and the steps counted in a `u64` so that long running mazes
+/// cannot overflow them, and the result tells which end the pointer left by.
+///
+/// Rules turning 2 into 3 and 3 into 2, like the Q2 one, are assumed to give the same result
+/// every time they are called. With them the cells at the start of the maze settle to a 2 or a
+/// 3 and stay that way: those are packed in bits, and the pointer goes through them a window at
+/// a time with a table of precomputed passes. In a release build Q2 of the puzzle input then
+/// takes about 47ms instead of 65ms with `execute`, and a maze of 10000 settled cells ending in
+/// three jumps back to the start 110ms instead of 175ms. Other rules run about as fast as
+/// `execute`, every jump waits for the offset loaded by the one before.
+pub fn escape<R: OffsetRule<i64> + ?Sized>(contents: &[i32], rule: &R) -> Escape {
+    let mut offsets: Vec<i64> = contents.iter().map(|&o| i64::from(o)).collect();
+    if rule.update(2) == 3 && rule.update(3) == 2 {
+        return escape_settling(offsets, rule);
+    }
+    let mut pointer: i64 = 0;
+    let mut steps: u64 = 0;
+
+    while let Some(offset) = usize::try_from(pointer).ok().and_then(|p| offsets.get_mut(p)) {
+        let jump = *offset;
+        *offset = rule.update(jump);
+        pointer += jump;
+        steps += 1;
+    }
+    let exit = if pointer < 0 { Exit::Start } else { Exit::End };
+    Escape{steps, exit, pointer}
+}
+
+fn escape_settling<R: OffsetRule<i64> + ?Sized>(mut offsets: Vec<i64>, rule: &R) -> Escape {
+    let passes: Vec<u32> = (0..1 << WINDOW).map(pass).collect();
+    // the cells below `settled` are only kept as bits of `words`, a set bit for a 3, with a
+    // spare word at the end so that a window can always be read from two of them
+    let mut words = vec![0u64; offsets.len() / 64 + 2];
+    let mut settled = 0;
+    let mut pointer: i64 = 0;
+    let mut steps: u64 = 0;
+
+    loop {
+        while settled < offsets.len() && (offsets[settled] == 2 || offsets[settled] == 3) {
+            if offsets[settled] == 3 {
+                words[settled / 64] |= 1 << (settled % 64);
+            }
+            settled += 1;
+        }
+        let mut p = match usize::try_from(pointer).ok().filter(|&p| p < offsets.len()) {
+            Some(p) => p,
+            None => break,
+        };
+
+        // Settled cells only send the pointer forward, a window at a time while all the cells
+        // it goes through are settled. The two words of cells it is in are kept at hand until
+        // it leaves the first one.
+        while p < settled {
+            let word = p / 64;
+            let limit = (settled - word * 64).min(128);
+            let (mut low, mut high) = (words[word], words[word + 1]);
+            let mut at = p % 64;
+            while at < 64 && at < limit {
+                // shifting by 1 then 63 - at gives 0 rather than overflowing when at is 0
+                let cells = (low >> at | high << 1 << (63 - at)) as usize;
+                let pass = passes[cells % (1 << WINDOW)];
+                let visited = u64::from(pass % (1 << 16));
+                if limit - at < WINDOW && visited >> (limit - at) != 0 {
+                    break;
+                }
+                low ^= visited << at;
+                high ^= visited >> 1 >> (63 - at);
+                at += (pass >> 16) as usize % 256;
+                steps += u64::from(pass >> 24);
+            }
+            words[word] = low;
+            words[word + 1] = high;
+            p = word * 64 + at;
+            if at < 64 {
+                break;
+            }
+        }
+        pointer = p as i64;
+        if p >= offsets.len() {
+            break;
+        }
+
+        if p < settled {
+            let bit = 1 << (p % 64);
+            pointer += if words[p / 64] & bit == 0 { 2 } else { 3 };
+            words[p / 64] ^= bit;
+        } else {
+            let jump = offsets[p];
+            offsets[p] = rule.update(jump);
+            pointer += jump;
+        }
+        steps += 1;
+    }
+    let exit = if pointer < 0 { Exit::Start } else { Exit::End };
+    Escape{steps, exit, pointer}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{execute, read, Increment, TableRule, ThresholdDecrement};
+    use crate::random::Random;
+    use rstest::rstest;
+
+    #[rstest(input, steps, exit, pointer,
+    case(&[0, 3, 0, 1, -3], 5, Exit::End, 5),
+    case(&[-1], 1, Exit::Start, -1),
+    case(&[1, -3], 2, Exit::Start, -2),
+    case(&[], 0, Exit::End, 0),
+    )]
+    fn test_escape(input: &[i32], steps: u64, exit: Exit, pointer: i64) {
+        assert_eq!(escape(input, &Increment), Escape{steps, exit, pointer});
+    }
+
+    #[test]
+    fn test_same_as_execute() {
+        let contents = read("day_05/input.txt").unwrap();
+        assert_eq!(escape(&contents, &Increment).steps, u64::from(execute(&contents, &Increment)));
+        assert_eq!(escape(&contents, &ThresholdDecrement(3)).steps, 23948711);
+
+        let rule = TableRule::parse(">=2:-2,*:+1").unwrap();
+        let contents = [0, 3, 0, 1, -3, 2, -1];
+        assert_eq!(escape(&contents, &rule).steps, u64::from(execute(&contents, &rule)));
+    }
+
+    #[test]
+    fn test_same_as_execute_generated() {
+        let mut random = Random::new(5);
+        for _ in 0..200 {
+            let len = random.between(&(1..=20i32));
+            let contents: Vec<i32> = (0..len).map(|_| random.between(&(0..=2 * len - 1)) - len).collect();
+            // offsets only grow so the pointer always gets out eventually
+            let rv = escape(&contents, &Increment);
+            assert_eq!(rv.steps, u64::from(execute(&contents, &Increment)), "{:?}", contents);
+            assert_eq!(rv.exit == Exit::Start, rv.pointer < 0);
+        }
+    }
+
+    #[test]
+    fn test_settling_same_as_execute_generated() {
+        let mut random = Random::new(36);
+        let table = TableRule::parse("=2:+1,=3:-1,*:+1").unwrap();
+        for _ in 0..50 {
+            // long runs of cells settling to 2 or 3, with a few jumping back over them
+            let len = random.between(&(1..=500i32));
+            let mut contents: Vec<i32> = (0..len).map(|i| match i % 16 {
+                0 => -random.between(&(0..=len - 1)),
+                n => 2 + n % 2,
+            }).collect();
+            random.shuffle(&mut contents);
+            // negative offsets grow until they are not, the others stay at 0 or above
+            let rule = ThresholdDecrement(3);
+            assert_eq!(escape(&contents, &rule).steps, u64::from(execute(&contents, &rule)), "{:?}", contents);
+            assert_eq!(escape(&contents, &table).steps, u64::from(execute(&contents, &table)), "{:?}", contents);
+        }
+    }
+
+    #[test]
+    fn test_wide_offsets() {
+        // the second jump is far past what an i32 can hold
+        let rule = |offset: i64| offset + (1 << 40);
+        assert_eq!(escape(&[0, 1], &rule), Escape{steps: 2, exit: Exit::End, pointer: 1 << 40});
+    }
+}
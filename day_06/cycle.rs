@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// How to look for the cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// remember every state, the fastest but memory grows with the number of steps
+    History,
+    /// Floyd's tortoise and hare, two states in memory
+    Floyd,
+    /// Brent's algorithm, two states in memory and fewer steps than Floyd
+    Brent,
+}
+
+/// Where a sequence of states starts repeating itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    /// steps until a state is seen for the second time, `start + length`
+    pub first_repeat: usize,
+    /// steps between two occurrences of the same state
+    pub length: usize,
+    /// steps before the first state of the cycle
+    pub start: usize,
+}
+
+impl Cycle {
+    fn new(start: usize, length: usize) -> Cycle {
+        Cycle{first_repeat: start + length, length, start}
+    }
+}
+
+/// The cycle of the sequence `initial`, `step(initial)`, `step(step(initial))`...
+/// Never returns if the sequence does not cycle, which cannot happen with a finite number of states.
+pub fn find_cycle<T, F>(initial: T, step: F, strategy: Strategy) -> Cycle
+    where T: Clone + Eq + Hash, F: Fn(&T) -> T {
+    match strategy {
+        Strategy::History => history(initial, step),
+        Strategy::Floyd => floyd(initial, step),
+        Strategy::Brent => brent(initial, step),
+    }
+}
+
+pub fn history<T, F>(initial: T, step: F) -> Cycle
+    where T: Clone + Eq + Hash, F: Fn(&T) -> T {
+    history_by(initial, step, T::clone)
+}
+
+/// Like `history` but remembers `key(state)` instead of the states themselves.
+/// `key` must give different keys to different states, a smaller key saves memory and hashing.
+pub fn history_by<T, F, K, G>(initial: T, step: F, key: G) -> Cycle
+    where F: Fn(&T) -> T, K: Eq + Hash, G: Fn(&T) -> K {
+    let mut seen: HashMap<K, usize> = HashMap::new();
+    let mut current = initial;
+    let mut i = 0;
+
+    loop {
+        let k = key(&current);
+        if let Some(&start) = seen.get(&k) {
+            return Cycle::new(start, i - start);
+        }
+        seen.insert(k, i);
+        current = step(&current);
+        i += 1;
+    }
+}
+
+pub fn floyd<T, F>(initial: T, step: F) -> Cycle
+    where T: Clone + Eq, F: Fn(&T) -> T {
+    // the hare goes twice as fast, they meet somewhere in the cycle
+    let mut tortoise = step(&initial);
+    let mut hare = step(&tortoise);
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&step(&hare));
+    }
+
+    // the hare is now a multiple of the cycle length ahead: start the tortoise again and
+    // move both at the same speed, they meet at the start of the cycle
+    let mut start = 0;
+    tortoise = initial;
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        start += 1;
+    }
+
+    let mut length = 1;
+    hare = step(&tortoise);
+    while tortoise != hare {
+        hare = step(&hare);
+        length += 1;
+    }
+    Cycle::new(start, length)
+}
+
+pub fn brent<T, F>(initial: T, step: F) -> Cycle
+    where T: Clone + Eq, F: Fn(&T) -> T {
+    // the tortoise teleports to the hare every power of two steps, until the hare comes back to it
+    let mut power = 1;
+    let mut length = 1;
+    let mut tortoise = initial.clone();
+    let mut hare = step(&initial);
+    while tortoise != hare {
+        if power == length {
+            tortoise = hare.clone();
+            power *= 2;
+            length = 0;
+        }
+        hare = step(&hare);
+        length += 1;
+    }
+
+    // start both again, `length` steps apart, they meet at the start of the cycle
+    let mut start = 0;
+    tortoise = initial.clone();
+    hare = initial;
+    for _ in 0..length {
+        hare = step(&hare);
+    }
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        start += 1;
+    }
+    Cycle::new(start, length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const STRATEGIES: [Strategy; 3] = [Strategy::History, Strategy::Floyd, Strategy::Brent];
+
+    #[rstest(next, initial, expected,
+    // 0, 1, ..., 10, then back to 3
+    case(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 3], 0, Cycle{first_repeat: 11, length: 8, start: 3}),
+    case(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 3], 3, Cycle{first_repeat: 8, length: 8, start: 0}),
+    case(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 3], 9, Cycle{first_repeat: 8, length: 8, start: 0}),
+    case(&[1, 2, 0], 0, Cycle{first_repeat: 3, length: 3, start: 0}),
+    case(&[4, 2, 3, 1, 1], 0, Cycle{first_repeat: 5, length: 3, start: 2}),
+    )]
+    fn test_find_cycle(next: &[usize], initial: usize, expected: Cycle) {
+        for &strategy in STRATEGIES.iter() {
+            assert_eq!(find_cycle(initial, |&x| next[x], strategy), expected, "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        for &strategy in STRATEGIES.iter() {
+            assert_eq!(find_cycle(7, |_: &u32| 7, strategy), Cycle{first_repeat: 1, length: 1, start: 0});
+            assert_eq!(find_cycle(1, |_: &u32| 7, strategy), Cycle{first_repeat: 2, length: 1, start: 1});
+        }
+    }
+
+    #[test]
+    fn test_history_by() {
+        let next = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 3];
+        let expected = Cycle{first_repeat: 11, length: 8, start: 3};
+        assert_eq!(history_by(0, |&x: &usize| next[x], |&x| x as u8), expected);
+        assert_eq!(history_by(0, |&x: &usize| next[x], |x| format!("{}", x)), expected);
+    }
+
+    #[test]
+    fn test_strategies_agree() {
+        for modulus in 2..200u64 {
+            for initial in 0..5 {
+                let step = |&x: &u64| (x * x + 1) % modulus;
+                let expected = find_cycle(initial, step, Strategy::History);
+                assert_eq!(find_cycle(initial, step, Strategy::Floyd), expected, "{} {}", modulus, initial);
+                assert_eq!(find_cycle(initial, step, Strategy::Brent), expected, "{} {}", modulus, initial);
+            }
+        }
+    }
+}
@@ -1,84 +1,485 @@
 use std::{io, env};
 use std::fs::File;
-use std::io::Read;
-use std::collections::HashMap;
+use std::io::{BufWriter, Read, Write};
+use std::iter;
+use std::process::exit;
+use std::time::Instant;
 
-fn read(path: &str) -> io::Result<Vec<u32>> {
+mod cycle;
+#[cfg(test)]
+#[path = "../day_04/random.rs"]
+mod random;
+
+use cycle::{find_cycle, history_by, Cycle, Strategy};
+
+/// Each bank holds up to `u32::MAX` blocks, they are widened to `u64` so that a bank can hold
+/// the blocks of all the others.
+fn read(path: &str) -> io::Result<Vec<u64>> {
     let mut file = File::open(path)?;
     let mut content = String::new();
     let mut rv = Vec::new();
     file.read_to_string(&mut content)?;
 
     for value in content.replace("\t", " ").split_whitespace() {
-        rv.push(value.parse().unwrap());
+        rv.push(u64::from(value.parse::<u32>().unwrap()));
     }
     Ok(rv)
 }
 
-fn redistribution_cycle(bank: &mut Vec<u32>) {
-    let max_value = bank.iter().max().unwrap_or(&0);
-    let mut p = bank.iter().position(|&x| x == *max_value).unwrap();
+/// Which bank to empty when several of them hold the most blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TieBreak {
+    /// the puzzle's rule
+    Lowest,
+    Highest,
+    /// picked from the seed and the banks: the same banks always give the same choice so the
+    /// redistribution is still a function of the banks and the cycle can be searched for
+    Random(u64),
+}
+
+// splitmix64 finalizer
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn choose(bank: &[u64], tie: TieBreak) -> Option<usize> {
+    let max_value = *bank.iter().max()?;
+    let mut tied = bank.iter().enumerate().filter(|&(_, &x)| x == max_value).map(|(p, _)| p);
+    match tie {
+        TieBreak::Lowest => tied.next(),
+        TieBreak::Highest => tied.next_back(),
+        TieBreak::Random(seed) => {
+            let hash = bank.iter().fold(mix(seed), |h, &x| mix(h ^ x));
+            let count = bank.iter().filter(|&&x| x == max_value).count();
+            tied.nth((hash % count as u64) as usize)
+        },
+    }
+}
 
-    let mut entries = bank[p];  // copy
+/// Empty the chosen bank and spread its blocks over the next ones, all at once: every bank
+/// gets the same share and the remainder goes one block each to the banks that follow.
+fn redistribution_cycle(bank: &mut [u64], tie: TieBreak) {
+    let p = match choose(bank, tie) {
+        Some(p) => p,
+        None => return,
+    };
+    let entries = bank[p];  // copy
     bank[p] = 0;
 
-    while entries > 0 {
-        p = (p + 1) % bank.len();
-        bank[p] += 1;
-        entries -= 1;
+    let len = bank.len();
+    let share = entries / len as u64;
+    let remainder = (entries % len as u64) as usize;
+    for b in bank.iter_mut() {
+        *b += share;
+    }
+    for i in 1..=remainder {
+        bank[(p + i) % len] += 1;
     }
 }
 
-fn search_loop(bank: &[u32]) -> (usize, usize) {
-    let mut i = 0;
-    let mut data = bank.to_vec();
-    // let mut history: HashSet<String> = HashSet::new(); // q1 was solved with hashset
-    let mut history: HashMap<String, usize> = HashMap::new();
+fn step(tie: TieBreak) -> impl Fn(&Vec<u64>) -> Vec<u64> + Copy {
+    move |data| {
+        let mut next = data.clone();
+        redistribution_cycle(&mut next, tie);
+        next
+    }
+}
 
-    let mut current = format!("{:?}", data);
+/// How many bits each bank gets to pack them all in a `u128`, None if they do not fit.
+/// The blocks are only moved around so no bank can ever hold more than the total.
+fn packing(bank: &[u64]) -> Option<usize> {
+    if bank.is_empty() || bank.len() > 128 {
+        return None;
+    }
+    let bits = (128 / bank.len()).min(64);
+    let total: u128 = bank.iter().map(|&b| u128::from(b)).sum();
+    if total >> bits == 0 {
+        Some(bits)
+    } else {
+        None
+    }
+}
 
-    while !history.contains_key(&current) {
-        history.insert(current, i);
+fn pack(bank: &[u64], bits: usize) -> u128 {
+    bank.iter().fold(0, |rv, &b| rv << bits | u128::from(b))
+}
 
-        redistribution_cycle(&mut data);
-        current = format!("{:?}", data);
-        i += 1;
+fn search(bank: &[u64], strategy: Strategy, tie: TieBreak) -> Cycle {
+    let step = step(tie);
+    match (strategy, packing(bank)) {
+        // no need to keep every bank when they fit in a number
+        (Strategy::History, Some(bits)) => history_by(bank.to_vec(), step, |data| pack(data, bits)),
+        _ => find_cycle(bank.to_vec(), step, strategy),
     }
+}
 
-    (i, i - history[&current])
+/// Every configuration of the banks, starting with `bank`.
+fn states(bank: &[u64], tie: TieBreak) -> impl Iterator<Item = Vec<u64>> {
+    let step = step(tie);
+    iter::successors(Some(bank.to_vec()), move |data| Some(step(data)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    /// one JSON object per line
+    Json,
+}
+
+/// Write the configurations from `bank` to the first repeated one, in `cycle`.
+/// The first state of the cycle is marked `cycle-start` and its repetition `first-repeat`.
+fn export<W: Write>(writer: &mut W, bank: &[u64], tie: TieBreak, cycle: Cycle, format: Format) -> io::Result<()> {
+    if format == Format::Csv {
+        let columns: Vec<String> = (0..bank.len()).map(|i| format!("bank_{}", i)).collect();
+        writeln!(writer, "step,mark,{}", columns.join(","))?;
+    }
+    for (i, data) in states(bank, tie).take(cycle.first_repeat + 1).enumerate() {
+        let mark = if i == cycle.first_repeat {
+            Some("first-repeat")
+        } else if i == cycle.start {
+            Some("cycle-start")
+        } else {
+            None
+        };
+        let values: Vec<String> = data.iter().map(|b| b.to_string()).collect();
+        match format {
+            Format::Csv => writeln!(writer, "{},{},{}", i, mark.unwrap_or(""), values.join(","))?,
+            Format::Json => {
+                let mark = mark.map_or("null".to_string(), |m| format!("\"{}\"", m));
+                writeln!(writer, "{{\"step\": {}, \"mark\": {}, \"banks\": [{}]}}", i, mark, values.join(", "))?
+            },
+        }
+    }
+    writer.flush()
+}
+
+/// Time the history with the different keys.
+fn bench(bank: &[u64]) {
+    let step = step(TieBreak::Lowest);
+    let time = |name: &str, search: &dyn Fn() -> Cycle| {
+        let start = Instant::now();
+        let cycle = search();
+        println!("{:>8} keys: looped in {} cycles (loop size is {}) in {:?}",
+                 name, cycle.first_repeat, cycle.length, start.elapsed());
+    };
+
+    time("string", &|| history_by(bank.to_vec(), step, |data| format!("{:?}", data)));
+    time("vector", &|| history_by(bank.to_vec(), step, Vec::clone));
+    match packing(bank) {
+        Some(bits) => time("packed", &|| history_by(bank.to_vec(), step, |data| pack(data, bits))),
+        None => println!("  packed keys: {} banks do not fit in 128 bits", bank.len()),
+    }
+}
+
+struct Options {
+    strategy: Strategy,
+    tie: TieBreak,
+    bench: Option<usize>,
+    export: Option<String>,
+    format: Format,
+}
+
+// usage: day_06 PATH [--strategy history|floyd|brent] [--tie lowest|highest|random [--seed N]] [--bench BANKS]
+//                    [--export PATH [--format csv|json]]
+impl Options {
+    fn from_args(args: &[String]) -> Result<Options, String> {
+        let mut rv = Options{
+            strategy: Strategy::History,
+            tie: TieBreak::Lowest,
+            bench: None,
+            export: None,
+            format: Format::Csv,
+        };
+        let mut seed = None;
+        let mut format = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--strategy" => rv.strategy = match value()?.as_str() {
+                    "history" => Strategy::History,
+                    "floyd" => Strategy::Floyd,
+                    "brent" => Strategy::Brent,
+                    other => return Err(format!("unknown strategy {}", other)),
+                },
+                "--tie" => rv.tie = match value()?.as_str() {
+                    "lowest" => TieBreak::Lowest,
+                    "highest" => TieBreak::Highest,
+                    "random" => TieBreak::Random(0),
+                    other => return Err(format!("unknown tie break {}", other)),
+                },
+                "--seed" => seed = Some(value()?.parse().map_err(|_| "--seed needs a number")?),
+                "--bench" => rv.bench = Some(value()?.parse().map_err(|_| "--bench needs a number")?),
+                "--export" => rv.export = Some(value()?.clone()),
+                "--format" => format = Some(match value()?.as_str() {
+                    "csv" => Format::Csv,
+                    "json" => Format::Json,
+                    other => return Err(format!("unknown format {}", other)),
+                }),
+                other => return Err(format!("unknown argument {}", other)),
+            }
+        }
+        match (&rv.export, format) {
+            (Some(_), Some(format)) => rv.format = format,
+            (None, Some(_)) => return Err("--format needs --export".to_string()),
+            _ => {},
+        }
+        match (rv.tie, seed) {
+            (TieBreak::Random(_), Some(seed)) => rv.tie = TieBreak::Random(seed),
+            (_, Some(_)) => return Err("--seed needs --tie random".to_string()),
+            _ => {},
+        }
+        Ok(rv)
+    }
 }
 
 fn main() {
     let path = env::args().nth(1).expect("please supply a path");
+    let args: Vec<String> = env::args().skip(2).collect();
+    let options = match Options::from_args(&args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("invalid arguments: {}", e);
+            exit(1);
+        },
+    };
     let contents = read(&path).expect("no content");
+    if let Some(banks) = options.bench {
+        println!("{} banks from {}:", contents.len(), path);
+        bench(&contents);
+        // 0 to 15 blocks in each bank
+        let bank: Vec<u64> = (0..banks).map(|i| (i * 7 + 3) as u64 % 16).collect();
+        println!("{} generated banks:", banks);
+        bench(&bank);
+        return;
+    }
 
     println!("Initial state: {:?}", contents);
-    let cycles = search_loop(&contents);
-    println!("Looped in {} cycles (loop size is {})", cycles.0, cycles.1);
+    let cycle = search(&contents, options.strategy, options.tie);
+    println!("Looped in {} cycles (loop size is {})", cycle.first_repeat, cycle.length);
+
+    if let Some(export_path) = &options.export {
+        let rv = File::create(export_path)
+            .and_then(|f| export(&mut BufWriter::new(f), &contents, options.tie, cycle, options.format));
+        if let Err(e) = rv {
+            eprintln!("cannot export to {}: {}", export_path, e);
+            exit(1);
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::random::Random;
     use rstest::rstest;
 
+    // the most blocks a bank of the input can hold
+    const MAX: u64 = u32::MAX as u64;
+
     #[rstest(bank, output,
     case(&[0, 2, 7, 0], &[2, 4, 1, 2]),
     case(&[2, 4, 1, 2], &[3, 1, 2, 3]),
     case(&[0, 2, 3, 4], &[1, 3, 4, 1]),
     case(&[1, 3, 4, 1], &[2, 4, 1, 2]),
     )]
-    fn test_redistribution_cycle(bank: &[u32], output: &[u32]) {
+    fn test_redistribution_cycle(bank: &[u64], output: &[u64]) {
         let mut data = bank.to_vec();
-        redistribution_cycle(&mut data);
+        redistribution_cycle(&mut data, TieBreak::Lowest);
         assert_eq!(data, output);
     }
 
+    #[rstest(bank, tie, output,
+    case(&[3, 1, 3, 0], TieBreak::Lowest, &[0, 2, 4, 1]),
+    case(&[3, 1, 3, 0], TieBreak::Highest, &[4, 2, 0, 1]),
+    case(&[0, 2, 7, 0], TieBreak::Highest, &[2, 4, 1, 2]),
+    case(&[10, 0, 0], TieBreak::Lowest, &[3, 4, 3]),
+    case(&[3_000_000_000, 1, 2], TieBreak::Lowest, &[1_000_000_000, 1_000_000_001, 1_000_000_002]),
+    case(&[MAX], TieBreak::Lowest, &[MAX]),
+    // more blocks than a u32 can hold
+    case(&[4_000_000_000, 4_000_000_000, 1], TieBreak::Lowest, &[1_333_333_333, 5_333_333_334, 1_333_333_334]),
+    case(&[], TieBreak::Lowest, &[]),
+    )]
+    fn test_redistribution_tie(bank: &[u64], tie: TieBreak, output: &[u64]) {
+        let mut data = bank.to_vec();
+        redistribution_cycle(&mut data, tie);
+        assert_eq!(data, output);
+    }
+
+    #[test]
+    fn test_redistribution_bulk() {
+        // the same as handing out the blocks one at a time
+        let mut random = Random::new(40);
+        for _ in 0..500 {
+            // half of the banks as full as can be so that they tie for the most blocks
+            let len = random.between(&(1..=10usize));
+            let mut bank: Vec<u64> = (0..len).map(|i| if i < len / 2 { 49 } else { random.between(&(0..=49)) }).collect();
+            random.shuffle(&mut bank);
+            let mut expected = bank.clone();
+            let mut p = choose(&expected, TieBreak::Lowest).unwrap();
+            let mut entries = expected[p];
+            expected[p] = 0;
+            while entries > 0 {
+                p = (p + 1) % expected.len();
+                expected[p] += 1;
+                entries -= 1;
+            }
+            let mut data = bank.clone();
+            redistribution_cycle(&mut data, TieBreak::Lowest);
+            assert_eq!(data, expected, "{:?}", bank);
+        }
+    }
+
+    #[test]
+    fn test_random_tie() {
+        let lowest = [0, 2, 4, 1];
+        let highest = [4, 2, 0, 1];
+        let mut picked = Vec::new();
+        for seed in 0..50 {
+            let mut data = vec!(3, 1, 3, 0);
+            redistribution_cycle(&mut data, TieBreak::Random(seed));
+            assert!(data == lowest || data == highest, "{:?}", data);
+            // same seed and banks, same choice
+            let mut again = vec!(3, 1, 3, 0);
+            redistribution_cycle(&mut again, TieBreak::Random(seed));
+            assert_eq!(data, again);
+            picked.push(data == lowest);
+        }
+        assert!(picked.contains(&true) && picked.contains(&false));
+
+        // still a function of the banks so all the strategies agree
+        let contents = read("day_06/input.txt").unwrap();
+        let expected = search(&contents, Strategy::Floyd, TieBreak::Random(3));
+        assert_eq!(search(&contents, Strategy::History, TieBreak::Random(3)), expected);
+        assert_eq!(search(&contents, Strategy::Brent, TieBreak::Random(3)), expected);
+    }
+
     #[rstest(bank, expected,
-    case(&[0, 2, 7, 0], (5, 4)),
+    case(&[0, 2, 7, 0], Cycle{first_repeat: 5, length: 4, start: 1}),
+    case(&[4_000_000_000, 4_000_000_000, 1], Cycle{first_repeat: 46, length: 3, start: 43}),
+    )]
+    fn test_search(bank: &[u64], expected: Cycle) {
+        for &strategy in [Strategy::History, Strategy::Floyd, Strategy::Brent].iter() {
+            let rv = search(bank, strategy, TieBreak::Lowest);
+            assert_eq!(rv, expected);
+        }
+    }
+
+    #[test]
+    fn test_search_input() {
+        let contents = read("day_06/input.txt").unwrap();
+        let expected = Cycle{first_repeat: 7864, length: 1695, start: 6169};
+        assert_eq!(search(&contents, Strategy::History, TieBreak::Lowest), expected);
+        assert_eq!(search(&contents, Strategy::Brent, TieBreak::Lowest), expected);
+    }
+
+    #[rstest(bank, bits,
+    case(&[0, 2, 7, 0], Some(32)),
+    case(&[MAX], Some(64)),
+    case(&[MAX, MAX, 1], Some(42)),
+    case(&[255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], Some(8)),
+    case(&[255, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], None),
+    case(&[0; 128], Some(1)),
+    case(&[0; 129], None),
+    case(&[], None),
+    )]
+    fn test_packing(bank: &[u64], bits: Option<usize>) {
+        assert_eq!(packing(bank), bits);
+    }
+
+    #[test]
+    fn test_pack() {
+        assert_eq!(pack(&[1, 2], 64), 1 << 64 | 2);
+        assert_ne!(pack(&[1, 0, 0], 42), pack(&[0, 1, 0], 42));
+        assert_ne!(pack(&[0, 0, 1], 42), pack(&[0, 1, 0], 42));
+    }
+
+    #[test]
+    fn test_packed_history() {
+        // the packed keys give the same answers as keeping the banks
+        let mut random = Random::new(6);
+        let step = step(TieBreak::Lowest);
+        for _ in 0..200 {
+            let bank: Vec<u64> = (0..random.between(&(1..=12))).map(|_| random.between(&(0..=19))).collect();
+            let bits = packing(&bank).unwrap();
+            assert_eq!(history_by(bank.clone(), step, |data| pack(data, bits)),
+                       history_by(bank.clone(), step, Vec::clone), "{:?}", bank);
+        }
+    }
+
+    #[test]
+    fn test_states() {
+        let rv: Vec<Vec<u64>> = states(&[0, 2, 7, 0], TieBreak::Lowest).take(3).collect();
+        assert_eq!(rv, vec!(vec!(0, 2, 7, 0), vec!(2, 4, 1, 2), vec!(3, 1, 2, 3)));
+    }
+
+    #[rstest(format, expected,
+    case(Format::Csv, "step,mark,bank_0,bank_1,bank_2,bank_3\n\
+                       0,,0,2,7,0\n\
+                       1,cycle-start,2,4,1,2\n\
+                       2,,3,1,2,3\n\
+                       3,,0,2,3,4\n\
+                       4,,1,3,4,1\n\
+                       5,first-repeat,2,4,1,2\n"),
+    case(Format::Json, "{\"step\": 0, \"mark\": null, \"banks\": [0, 2, 7, 0]}\n\
+                        {\"step\": 1, \"mark\": \"cycle-start\", \"banks\": [2, 4, 1, 2]}\n\
+                        {\"step\": 2, \"mark\": null, \"banks\": [3, 1, 2, 3]}\n\
+                        {\"step\": 3, \"mark\": null, \"banks\": [0, 2, 3, 4]}\n\
+                        {\"step\": 4, \"mark\": null, \"banks\": [1, 3, 4, 1]}\n\
+                        {\"step\": 5, \"mark\": \"first-repeat\", \"banks\": [2, 4, 1, 2]}\n"),
+    )]
+    fn test_export(format: Format, expected: &str) {
+        let bank = [0, 2, 7, 0];
+        let cycle = search(&bank, Strategy::History, TieBreak::Lowest);
+        let mut rv = Vec::new();
+        export(&mut rv, &bank, TieBreak::Lowest, cycle, format).unwrap();
+        assert_eq!(String::from_utf8(rv).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_export_cycle_from_start() {
+        // the first state is the start of the cycle
+        let bank = [2, 4, 1, 2];
+        let cycle = search(&bank, Strategy::Floyd, TieBreak::Lowest);
+        let mut rv = Vec::new();
+        export(&mut rv, &bank, TieBreak::Lowest, cycle, Format::Csv).unwrap();
+        let rv = String::from_utf8(rv).unwrap();
+        let lines: Vec<&str> = rv.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[1], "0,cycle-start,2,4,1,2");
+        assert_eq!(lines[5], "4,first-repeat,2,4,1,2");
+    }
+
+    #[rstest(args, strategy, tie,
+    case(&[], Strategy::History, TieBreak::Lowest),
+    case(&["--strategy", "floyd"], Strategy::Floyd, TieBreak::Lowest),
+    case(&["--strategy", "brent", "--tie", "highest"], Strategy::Brent, TieBreak::Highest),
+    case(&["--tie", "random"], Strategy::History, TieBreak::Random(0)),
+    case(&["--seed", "12", "--tie", "random"], Strategy::History, TieBreak::Random(12)),
+    )]
+    fn test_options(args: &[&str], strategy: Strategy, tie: TieBreak) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let options = Options::from_args(&args).unwrap();
+        assert_eq!(options.strategy, strategy);
+        assert_eq!(options.tie, tie);
+    }
+
+    #[rstest(args,
+    case(&["--strategy"]),
+    case(&["--strategy", "hash"]),
+    case(&["--bench", "many"]),
+    case(&["--tie", "middle"]),
+    case(&["--seed", "3"]),
+    case(&["--tie", "highest", "--seed", "3"]),
+    case(&["--format", "json"]),
+    case(&["--export", "out.csv", "--format", "xml"]),
+    case(&["--verbose"]),
     )]
-    fn test_search_loop(bank: &[u32], expected: (usize, usize)) {
-        let rv = search_loop(bank);
-        assert_eq!(rv, expected);
+    fn test_options_error(args: &[&str]) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        assert!(Options::from_args(&args).is_err());
     }
 }
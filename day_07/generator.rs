@@ -0,0 +1,379 @@
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+use crate::Correction;
+use crate::random::Random;
+
+// how many towers to try before giving up on placing the wrong weight
+const ATTEMPTS: usize = 100;
+
+/// A generated puzzle input with what solving it should give.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Generated {
+    /// one program per line, in random order
+    pub text: String,
+    pub root: String,
+    /// the wrong weight put in the tower, if asked for
+    pub correction: Option<Correction>,
+}
+
+// Disjoint ranges of total weights, in increasing order.
+type Totals = Vec<RangeInclusive<u64>>;
+
+fn intersect(a: &[RangeInclusive<u64>], b: &[RangeInclusive<u64>]) -> Totals {
+    let mut rv = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let start = *a[i].start().max(b[j].start());
+        let end = *a[i].end().min(b[j].end());
+        if start <= end {
+            rv.push(start..=end);
+        }
+        if a[i].end() < b[j].end() {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    rv
+}
+
+// a program of the tower being built, the index of its parent is always lower
+struct Node {
+    name: String,
+    parent: Option<usize>,
+    depth: usize,
+    children: Vec<usize>,
+    weight: u64,
+    total: u64,
+    // what the total of the program can be with every weight in the range, kept under u32::MAX
+    totals: Totals,
+    // what each of the programs above can weigh so that they all weigh the same
+    above: Totals,
+}
+
+pub struct Generator {
+    depth: usize,
+    branching: RangeInclusive<usize>,
+    weights: RangeInclusive<u32>,
+    random: Random,
+}
+
+impl Generator {
+    /// Towers with programs up to `depth` levels above the root, each one below that carrying
+    /// a number of programs in `branching`. All the weights are in `weights`, the wrong one too.
+    pub fn new(depth: usize, branching: RangeInclusive<usize>, weights: RangeInclusive<u32>, seed: u64) -> Generator {
+        Generator{
+            depth,
+            branching,
+            weights,
+            random: Random::new(seed),
+        }
+    }
+
+    fn name(&mut self, taken: &mut HashSet<String>) -> String {
+        loop {
+            let len = self.random.between(&(4..=7));
+            let name: String = (0..len).map(|_| (b'a' + self.random.below(26) as u8) as char).collect();
+            if taken.insert(name.clone()) {
+                return name;
+            }
+        }
+    }
+
+    fn pick(&mut self, totals: &[RangeInclusive<u64>]) -> u64 {
+        let range = &totals[self.random.below(totals.len())];
+        self.random.between(range)
+    }
+
+    // The totals a program carrying `count` programs that can each weigh `above` can have.
+    fn totals(&self, count: u64, above: &[RangeInclusive<u64>]) -> Totals {
+        let (low, high) = (u64::from(*self.weights.start()), u64::from(*self.weights.end()));
+        let mut rv: Totals = Vec::new();
+        let mut push = |start: u64, end: u64| {
+            let end = end.min(u64::from(u32::MAX));
+            if start > end {
+                return;
+            }
+            match rv.last_mut() {
+                Some(last) if *last.end() + 1 >= start => *last = *last.start()..=end.max(*last.end()),
+                _ => rv.push(start..=end),
+            }
+        };
+        for range in above {
+            if high - low + 1 >= count {
+                // the weights fill the gaps between the totals of the programs above
+                push(low + count * range.start(), high + count * range.end());
+            } else {
+                for each in range.clone() {
+                    if low + count * each > u64::from(u32::MAX) {
+                        break;
+                    }
+                    push(low + count * each, high + count * each);
+                }
+            }
+        }
+        rv
+    }
+
+    // A balanced tower, parents first, None if its shape cannot be balanced with weights in
+    // the range. The totals each program can have are found from the top, then the totals of
+    // the programs are picked from the root and their weights follow.
+    fn nodes(&mut self) -> Option<Vec<Node>> {
+        let mut taken = HashSet::new();
+        let mut rv = vec![Node{name: self.name(&mut taken), parent: None, depth: 0, children: Vec::new(), weight: 0, total: 0, totals: Vec::new(), above: Vec::new()}];
+        let mut i = 0;
+        while i < rv.len() {
+            if rv[i].depth < self.depth {
+                let count = self.random.between(&self.branching);
+                for _ in 0..count {
+                    let child = Node{
+                        name: self.name(&mut taken),
+                        parent: Some(i),
+                        depth: rv[i].depth + 1,
+                        children: Vec::new(),
+                        weight: 0,
+                        total: 0,
+                        totals: Vec::new(),
+                        above: Vec::new(),
+                    };
+                    let id = rv.len();
+                    rv[i].children.push(id);
+                    rv.push(child);
+                }
+            }
+            i += 1;
+        }
+
+        // children are after their parent, going backwards gets them done first
+        for i in (0..rv.len()).rev() {
+            let mut above = vec![0..=u64::from(u32::MAX)];
+            for &child in &rv[i].children {
+                above = intersect(&above, &rv[child].totals);
+            }
+            rv[i].totals = self.totals(rv[i].children.len() as u64, &above);
+            rv[i].above = above;
+        }
+        if rv[0].totals.is_empty() {
+            return None;
+        }
+
+        rv[0].total = self.pick(&rv[0].totals.clone());
+        for i in 0..rv.len() {
+            let children = rv[i].children.clone();
+            // the programs above weigh `each`, with total = weight + count * each
+            let (total, count) = (rv[i].total, children.len() as u64);
+            let (low, high) = (u64::from(*self.weights.start()), u64::from(*self.weights.end()));
+            let each = match total.saturating_sub(low).checked_div(count) {
+                // nothing above
+                None => 0,
+                Some(end) => {
+                    let start = total.saturating_sub(high).div_ceil(count);
+                    let possible = intersect(&rv[i].above, &[start..=end]);
+                    self.pick(&possible)
+                },
+            };
+            rv[i].weight = total - count * each;
+            for child in children {
+                rv[child].total = each;
+            }
+        }
+        Some(rv)
+    }
+
+    // whether `find_correction` can only point at `id` when its weight is wrong: it has
+    // siblings, and with a single one another program below has to be unbalanced too
+    fn can_be_found(nodes: &[Node], id: usize) -> bool {
+        let parent = match nodes[id].parent {
+            Some(parent) => parent,
+            None => return false,
+        };
+        let mut below = nodes[parent].parent;
+        let mut more = false;
+        while let Some(b) = below {
+            more |= nodes[b].children.len() > 1;
+            below = nodes[b].parent;
+        }
+        match nodes[parent].children.len() {
+            0 | 1 => false,
+            2 => more,
+            _ => true,
+        }
+    }
+
+    // change the weight of a program at `depth`, None if no program there would be found
+    fn add_fault(&mut self, nodes: &mut [Node], depth: usize) -> Option<Correction> {
+        let candidates: Vec<usize> = (0..nodes.len())
+            .filter(|&id| nodes[id].depth == depth && Self::can_be_found(nodes, id))
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let (low, high) = (u64::from(*self.weights.start()), u64::from(*self.weights.end()));
+        if low == high {
+            return None;
+        }
+        let id = candidates[self.random.below(candidates.len())];
+        let weight = nodes[id].weight;
+        // any other weight of the range
+        let mut wrong = self.random.between(&(low..=high - 1));
+        if wrong >= weight {
+            wrong += 1;
+        }
+
+        nodes[id].weight = wrong;
+        let mut current = Some(id);
+        while let Some(c) = current {
+            nodes[c].total = nodes[c].total + wrong - weight;
+            current = nodes[c].parent;
+        }
+        let parent = nodes[id].parent.unwrap();
+        Some(Correction{
+            name: nodes[id].name.clone(),
+            weight: wrong as u32,
+            expected: weight as u32,
+            siblings: nodes[parent].children.iter().map(|&c| (nodes[c].name.clone(), nodes[c].total as u32)).collect(),
+        })
+    }
+
+    /// A balanced tower, or one with a single wrong weight `fault` levels above the root.
+    /// None if no wrong weight could be placed so that it is the only correction, or if no
+    /// tower could be balanced with weights in the range and a total that fits in a `u32`.
+    pub fn generate(&mut self, fault: Option<usize>) -> Option<Generated> {
+        for _ in 0..ATTEMPTS {
+            let mut nodes = match self.nodes() {
+                Some(nodes) => nodes,
+                None => continue,
+            };
+            let correction = match fault {
+                Some(depth) => match self.add_fault(&mut nodes, depth) {
+                    Some(correction) => Some(correction),
+                    None => continue,
+                },
+                None => None,
+            };
+            if nodes[0].total > u64::from(u32::MAX) {
+                return None;
+            }
+
+            let mut lines: Vec<String> = nodes.iter()
+                .map(|node| {
+                    let mut line = format!("{} ({})", node.name, node.weight);
+                    if !node.children.is_empty() {
+                        let children: Vec<&str> = node.children.iter().map(|&c| nodes[c].name.as_str()).collect();
+                        line.push_str(&format!(" -> {}", children.join(", ")));
+                    }
+                    line
+                })
+                .collect();
+            self.random.shuffle(&mut lines);
+            return Some(Generated{
+                text: lines.join("\n") + "\n",
+                root: nodes[0].name.clone(),
+                correction,
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tower;
+    use rstest::rstest;
+
+    fn depth(tower: &Tower, name: &str) -> usize {
+        let mut rv = 0;
+        let mut current = tower[tower.id(name).unwrap()].parent;
+        while let Some(id) = current {
+            rv += 1;
+            current = tower[id].parent;
+        }
+        rv
+    }
+
+    #[test]
+    fn test_generate_is_reproducible() {
+        let mut a = Generator::new(3, 1..=4, 1..=50, 42);
+        let mut b = Generator::new(3, 1..=4, 1..=50, 42);
+        let mut c = Generator::new(3, 1..=4, 1..=50, 43);
+        for fault in &[None, Some(2)] {
+            let generated = a.generate(*fault);
+            assert_eq!(generated, b.generate(*fault));
+            assert_ne!(generated, c.generate(*fault));
+        }
+    }
+
+    #[rstest(depth, branching, weights,
+    case(0, 2..=3, 1..=9),
+    case(1, 1..=1, 5..=5),
+    case(3, 2..=4, 1..=100),
+    case(4, 0..=3, 0..=10),
+    case(6, 2..=2, 10..=20),
+    )]
+    fn test_generate_balanced(depth: usize, branching: RangeInclusive<usize>, weights: RangeInclusive<u32>) {
+        for seed in 0..30 {
+            let generated = Generator::new(depth, branching.clone(), weights.clone(), seed).generate(None).unwrap();
+            let tower = Tower::from_reader(generated.text.as_bytes()).unwrap();
+            assert_eq!(tower.validate(), Vec::new());
+            assert_eq!(tower.root_name(), Some(generated.root.as_str()));
+            assert!(tower.is_balanced(), "{}", generated.text);
+            assert_eq!(tower.find_correction(), Ok(None));
+            assert_eq!(generated.correction, None);
+
+            for program in &tower.programs {
+                assert!(weights.contains(&program.weight.unwrap()), "{}", generated.text);
+                let level = self::depth(&tower, &program.name);
+                assert!(level <= depth);
+                if level < depth {
+                    assert!(branching.contains(&program.children.len()));
+                } else {
+                    assert!(program.children.is_empty());
+                }
+            }
+        }
+    }
+
+    #[rstest(depth, branching, fault,
+    case(1, 3..=5, 1),
+    case(2, 2..=2, 2),
+    case(3, 2..=4, 1),
+    case(3, 2..=4, 3),
+    case(5, 1..=3, 4),
+    )]
+    fn test_generate_fault(depth: usize, branching: RangeInclusive<usize>, fault: usize) {
+        for seed in 0..30 {
+            let generated = Generator::new(depth, branching.clone(), 1..=100, seed).generate(Some(fault)).unwrap();
+            let mut tower = Tower::from_reader(generated.text.as_bytes()).unwrap();
+            assert_eq!(tower.validate(), Vec::new());
+            assert_eq!(tower.root_name(), Some(generated.root.as_str()));
+            assert!(!tower.is_balanced());
+
+            let correction = generated.correction.unwrap();
+            assert_eq!(tower.find_correction(), Ok(Some(correction.clone())), "{}", generated.text);
+            assert_eq!(self::depth(&tower, &correction.name), fault);
+            assert_ne!(correction.weight, correction.expected);
+            tower.set_weight(&correction.name, correction.expected).unwrap();
+            assert!(tower.is_balanced());
+        }
+    }
+
+    #[rstest(depth, branching, fault,
+    // the root has no siblings to compare with
+    case(3, 2..=3, 0),
+    case(2, 2..=3, 3),
+    // nothing to compare with
+    case(3, 1..=1, 2),
+    // two programs side by side, either one could be wrong
+    case(1, 2..=2, 1),
+    )]
+    fn test_generate_impossible(depth: usize, branching: RangeInclusive<usize>, fault: usize) {
+        assert_eq!(Generator::new(depth, branching, 1..=10, 1).generate(Some(fault)), None);
+    }
+
+    #[test]
+    fn test_generate_too_heavy() {
+        assert_eq!(Generator::new(2, 3..=3, u32::MAX..=u32::MAX, 1).generate(None), None);
+    }
+}
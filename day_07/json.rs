@@ -0,0 +1,348 @@
+use crate::{ProgramId, Tower};
+
+// A tower is written as nested programs, each one on its own line:
+//
+// {"name": "tknk", "weight": 41, "children": [
+//   {"name": "ugml", "weight": 68, "children": []},
+//   {"name": "padx", "weight": 45, "children": []}
+// ]}
+//
+// and an empty tower as `null`. Both reading and writing keep their own stack so that any
+// height of tower fits.
+
+fn quote(text: &str) -> String {
+    let mut rv = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => rv.push_str("\\\""),
+            '\\' => rv.push_str("\\\\"),
+            '\n' => rv.push_str("\\n"),
+            '\t' => rv.push_str("\\t"),
+            c if c.is_control() => rv.push_str(&format!("\\u{:04x}", c as u32)),
+            c => rv.push(c),
+        }
+    }
+    rv.push('"');
+    rv
+}
+
+/// The programs connected to the root as a JSON document, children in order.
+/// The tower must be one that `validate` finds nothing wrong with.
+pub fn to_json(tower: &Tower) -> String {
+    enum Step { Open(ProgramId, usize, bool), Close(usize, bool) }
+
+    let mut rv = String::new();
+    let mut stack = match tower.root {
+        Some(root) => vec![Step::Open(root, 0, true)],
+        None => return "null\n".to_string(),
+    };
+    while let Some(step) = stack.pop() {
+        let last = match step {
+            Step::Open(id, depth, last) => {
+                let program = &tower[id];
+                rv.push_str(&format!("{}{{\"name\": {}, \"weight\": {}, \"children\": [",
+                                     "  ".repeat(depth), quote(&program.name), program.weight.unwrap_or(0)));
+                if !program.children.is_empty() {
+                    rv.push('\n');
+                    stack.push(Step::Close(depth, last));
+                    for (i, &child) in program.children.iter().enumerate().rev() {
+                        stack.push(Step::Open(child, depth + 1, i + 1 == program.children.len()));
+                    }
+                    continue;
+                }
+                last
+            },
+            Step::Close(depth, last) => {
+                rv.push_str(&"  ".repeat(depth));
+                last
+            },
+        };
+        rv.push_str(if last { "]}\n" } else { "]},\n" });
+    }
+    rv
+}
+
+/// Read a tower written by `to_json`. Whitespace does not matter and the keys of a program
+/// can be in any order. `validate` tells about what is wrong with the programs themselves,
+/// with the line where each one starts.
+pub fn from_json(text: &str) -> Result<Tower, String> {
+    // what can come next
+    enum State { Start, AfterMember, AfterChild }
+
+    let mut parser = Parser{
+        chars: text.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    let mut rv = Tower::new();
+    if parser.consume_word("null") {
+        parser.expect_end()?;
+        return Ok(rv);
+    }
+    parser.expect('{')?;
+    let mut stack = vec![parser.frame()];
+    let mut state = State::Start;
+
+    while !stack.is_empty() {
+        let member = match state {
+            State::Start => !parser.consume('}'),
+            State::AfterMember if parser.consume(',') => true,
+            State::AfterMember if parser.consume('}') => false,
+            State::AfterMember => return Err(parser.error("',' or '}'")),
+            State::AfterChild if parser.consume(',') => {
+                parser.expect('{')?;
+                stack.push(parser.frame());
+                state = State::Start;
+                continue;
+            },
+            State::AfterChild if parser.consume(']') => {
+                state = State::AfterMember;
+                continue;
+            },
+            State::AfterChild => return Err(parser.error("',' or ']'")),
+        };
+
+        if member {
+            let frame = stack.last_mut().unwrap();
+            let key = parser.string()?;
+            parser.expect(':')?;
+            state = State::AfterMember;
+            match key.as_str() {
+                "name" if frame.name.is_none() => frame.name = Some(parser.string()?),
+                "weight" if frame.weight.is_none() => frame.weight = Some(parser.weight()?),
+                "children" => {
+                    parser.expect('[')?;
+                    if !parser.consume(']') {
+                        parser.expect('{')?;
+                        stack.push(parser.frame());
+                        state = State::Start;
+                    }
+                },
+                "name" | "weight" => return Err(format!("{} is given twice on line {}", key, parser.line)),
+                _ => return Err(format!("unknown key {} on line {}", quote(&key), parser.line)),
+            }
+            continue;
+        }
+
+        // the end of the program
+        let frame = stack.pop().unwrap();
+        let name = frame.name.ok_or(format!("the program on line {} has no name", frame.line))?;
+        let weight = frame.weight.ok_or(format!("{} on line {} has no weight", name, frame.line))?;
+        rv.add(frame.line, &name, weight, &frame.children);
+        if let Some(parent) = stack.last_mut() {
+            parent.children.push(name);
+            state = State::AfterChild;
+        }
+    }
+    parser.expect_end()?;
+    Ok(rv.with_totals())
+}
+
+// a program being read
+struct Frame {
+    line: usize,
+    name: Option<String>,
+    weight: Option<u32>,
+    children: Vec<String>,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    // the line of `pos`, from 1
+    line: usize,
+}
+
+impl Parser {
+    fn error(&self, expected: &str) -> String {
+        match self.chars.get(self.pos) {
+            Some(c) => format!("expected {} on line {} but found {:?}", expected, self.line, c),
+            None => format!("expected {} on line {} but the document ended", expected, self.line),
+        }
+    }
+
+    fn frame(&self) -> Frame {
+        Frame{line: self.line, name: None, weight: None, children: Vec::new()}
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let rv = self.chars.get(self.pos).cloned();
+        if rv == Some('\n') {
+            self.line += 1;
+        }
+        self.pos += 1;
+        rv
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.advance();
+        }
+    }
+
+    fn consume(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&expected) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn consume_word(&mut self, expected: &str) -> bool {
+        self.skip_whitespace();
+        let end = self.pos + expected.chars().count();
+        if end <= self.chars.len() && self.chars[self.pos..end].iter().cloned().eq(expected.chars()) {
+            self.pos = end;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.consume(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("'{}'", expected)))
+        }
+    }
+
+    fn expect_end(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.pos < self.chars.len() {
+            return Err(self.error("the end of the document"));
+        }
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut rv = String::new();
+        loop {
+            match self.advance() {
+                Some('"') => return Ok(rv),
+                Some('\\') => {
+                    let escaped = match self.advance() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let code: String = (0..4).filter_map(|_| self.advance()).collect();
+                            u32::from_str_radix(&code, 16).ok().and_then(std::char::from_u32)
+                                .ok_or(format!("invalid escape \\u{} on line {}", code, self.line))?
+                        },
+                        _ => return Err(format!("invalid escape on line {}", self.line)),
+                    };
+                    rv.push(escaped);
+                },
+                Some(c) => rv.push(c),
+                None => return Err(format!("a string is not closed on line {}", self.line)),
+            }
+        }
+    }
+
+    fn weight(&mut self) -> Result<u32, String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        match digits.parse() {
+            Ok(weight) if !self.chars.get(self.pos).is_some_and(|c| c.is_alphanumeric() || *c == '.') => Ok(weight),
+            _ => {
+                self.pos = start;
+                Err(self.error("a weight"))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn test_to_json() {
+        let tower = Tower::from_reader("a (1) -> b, c\nb (2) -> d\nc (3)\nd (4)".as_bytes()).unwrap();
+        assert_eq!(to_json(&tower), "\
+{\"name\": \"a\", \"weight\": 1, \"children\": [
+  {\"name\": \"b\", \"weight\": 2, \"children\": [
+    {\"name\": \"d\", \"weight\": 4, \"children\": []}
+  ]},
+  {\"name\": \"c\", \"weight\": 3, \"children\": []}
+]}
+");
+        assert_eq!(to_json(&Tower::new()), "null\n");
+        let tower = Tower::from_reader("a (1)".as_bytes()).unwrap();
+        assert_eq!(to_json(&tower), "{\"name\": \"a\", \"weight\": 1, \"children\": []}\n");
+    }
+
+    #[rstest(path,
+    case(&"day_07/test.txt"),
+    case(&"day_07/test_2.txt"),
+    case(&"day_07/test_3.txt"),
+    case(&"day_07/input.txt"),
+    )]
+    fn test_round_trip(path: &str) {
+        let tower = Tower::from_file(path).unwrap();
+        let json = to_json(&tower);
+        let loaded = from_json(&json).unwrap();
+        assert_eq!(loaded.validate(), Vec::new());
+        assert_eq!(to_json(&loaded), json);
+        assert_eq!(loaded.to_tree(None), tower.to_tree(None));
+        assert_eq!(loaded.find_correction(), tower.find_correction());
+    }
+
+    #[test]
+    fn test_from_json() {
+        let tower = from_json(" { \"children\" : [{\"weight\":2,\"name\":\"b\\u0021\"} ,\n\
+                                {\"name\": \"c\", \"children\": [], \"weight\": 2}],\n\
+                               \"name\": \"a \\\"quoted\\\"\", \"weight\": 10}\n").unwrap();
+        assert_eq!(tower.root_name(), Some("a \"quoted\""));
+        assert_eq!(tower.names(&tower[tower.root.unwrap()].children), vec!("b!", "c"));
+        assert_eq!(tower[tower.root.unwrap()].total_weight, 14);
+        assert_eq!(tower[tower.id("c").unwrap()].lines, vec!(2));
+        assert!(from_json(&to_json(&tower)).unwrap().is_balanced());
+        assert_eq!(from_json("null").unwrap().root, None);
+    }
+
+    #[test]
+    fn test_from_json_duplicate() {
+        let tower = from_json("{\"name\": \"a\", \"weight\": 1, \"children\": [\n\
+                               {\"name\": \"a\", \"weight\": 1, \"children\": []}]}").unwrap();
+        assert!(!tower.validate().is_empty());
+    }
+
+    #[rstest(text, error,
+    case("", "expected '{' on line 1 but the document ended"),
+    case("[]", "expected '{' on line 1 but found '['"),
+    case("{\"name\": \"a\"}", "a on line 1 has no weight"),
+    case("{\"weight\": 1}", "the program on line 1 has no name"),
+    case("{\"name\": \"a\", \"weight\": -1}", "expected a weight on line 1 but found '-'"),
+    case("{\"name\": \"a\", \"weight\": 1.5}", "expected a weight on line 1 but found '1'"),
+    case("{\"name\": \"a\", \"weight\": 4294967296}", "expected a weight on line 1 but found '4'"),
+    case("{\"name\": \"a\",\n\"name\": \"b\"}", "name is given twice on line 2"),
+    case("{\"name\": \"a\", \"size\": 1}", "unknown key \"size\" on line 1"),
+    case("{\"name\": \"a\" \"weight\": 1}", "expected ',' or '}' on line 1 but found '\"'"),
+    case("{\"name\": \"a\", \"weight\": 1, \"children\": [{\"name\": \"b\", \"weight\": 1} {",
+         "expected ',' or ']' on line 1 but found '{'"),
+    case("{\"name\": \"a\", \"weight\": 1, \"children\": [1]}", "expected '{' on line 1 but found '1'"),
+    case("{\"name\": \"a, \"weight\": 1}", "expected ',' or '}' on line 1 but found 'w'"),
+    case("{\"name\": \"a\\x\"}", "invalid escape on line 1"),
+    case("{\"name\": \"a", "a string is not closed on line 1"),
+    case("{\"name\": \"a\", \"weight\": 1}\n}", "expected the end of the document on line 2 but found '}'"),
+    case("null null", "expected the end of the document on line 1 but found 'n'"),
+    )]
+    fn test_from_json_error(text: &str, error: &str) {
+        assert_eq!(from_json(text).err(), Some(error.to_string()));
+    }
+}
@@ -1,273 +1,985 @@
 use std::{io, env};
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{BufReader, BufRead};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ops::{Index, IndexMut};
 use core::fmt;
 use std::process::exit;
 
+mod generator;
+mod json;
+mod parse;
+#[path = "../day_04/random.rs"]
+mod random;
+#[path = "../day_04/range.rs"]
+mod range;
+
+use generator::Generator;
+use parse::{LineError, ParseError};
+use range::parse_range;
+
+/// Handle on a program of a `Tower`, only meaningful for the tower that gave it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct ProgramId(usize);
+
 #[derive(Debug)]
 struct Program {
     name: String,
     weight: Option<u32>,
-    parent: Option<String>,
-    children: Vec<String>,
+    parent: Option<ProgramId>,
+    children: Vec<ProgramId>,
     total_weight: u32,
+    // the lines where the program is defined, more than one is a mistake
+    lines: Vec<usize>,
 }
 
 impl Program {
-    fn new(name: &str, weight: Option<u32>, parent: Option<String>) -> Program {
-        println!("  creating {} parent is {:?}", name, parent);
+    fn new(name: &str, weight: Option<u32>, parent: Option<ProgramId>) -> Program {
         Program{
             name: name.to_string(),
             weight,
             parent,
             children:Vec::new(),
             total_weight: 0,
+            lines: Vec::new(),
         }
     }
+}
+
+/// What can be wrong with the lines describing a tower.
+/// Lines are numbered from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Problem {
+    /// a child that is never defined, with the lines of its parents
+    Undefined { name: String, lines: Vec<usize> },
+    /// a program defined more than once
+    Duplicate { name: String, lines: Vec<usize> },
+    /// a child listed by more than one program, with the lines of the parents
+    MultipleParents { name: String, parents: Vec<String>, lines: Vec<usize> },
+    /// programs that are not connected to the root
+    Disconnected { programs: Vec<String>, lines: Vec<usize> },
+    /// programs standing on each other in a loop, each one a child of the previous
+    Cycle { programs: Vec<String>, lines: Vec<usize> },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines = |lines: &[usize]| {
+            let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+            format!("line {}", lines.join(", "))
+        };
+        match self {
+            Problem::Undefined { name, lines: l } =>
+                write!(f, "{}: {} is used but never defined", lines(l), name),
+            Problem::Duplicate { name, lines: l } =>
+                write!(f, "{}: {} is defined more than once", lines(l), name),
+            Problem::MultipleParents { name, parents, lines: l } =>
+                write!(f, "{}: {} is a child of {}", lines(l), name, parents.join(", ")),
+            Problem::Disconnected { programs, lines: l } =>
+                write!(f, "{}: {} not connected to the root", lines(l), programs.join(", ")),
+            Problem::Cycle { programs, lines: l } =>
+                write!(f, "{}: {} stand on each other", lines(l), programs.join(" -> ")),
+        }
+    }
+}
 
-    // fn total_weight(&self) -> u32 {
-    //     // it's a bug to be None but let's roll
-    //     self.weight.unwrap_or(0) + self.total_weight
-    // }
+/// A weight to change to balance the tower.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Correction {
+    name: String,
+    weight: u32,
+    /// the weight it should have
+    expected: u32,
+    /// total weights of the program and its siblings, in the order of their parent's children
+    siblings: Vec<(String, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CorrectionError {
+    /// any of these corrections balances the tower, nothing tells which one is right
+    /// (a program with exactly two children that do not weigh the same and no other clue)
+    Ambiguous(Vec<Correction>),
+    /// changing a single weight cannot balance the tower, without going below 0 at least
+    Impossible,
+}
+
+/// Why an edit of the tower was refused, the tower is left as it was.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum EditError {
+    /// no program has this name
+    Unknown(String),
+    /// a program with this name is already in the tower
+    Exists(String),
+    /// the program would stand on itself, `parent` being the program or one above it
+    Loop { name: String, parent: String },
+    /// the total weight of this program would not fit in a u32
+    Overflow(String),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EditError::Unknown(name) => write!(f, "there is no program named {}", name),
+            EditError::Exists(name) => write!(f, "{} is already in the tower", name),
+            EditError::Loop { name, parent } => write!(f, "{} cannot stand on {}, it is above {}", name, parent, name),
+            EditError::Overflow(name) => write!(f, "{} would weigh too much", name),
+        }
+    }
 }
 
+
 struct Tower {
     // the bottom of the tower
-    root: Option<String>,
-    // all the known programs
-    programs: HashMap<String, Program>,
+    root: Option<ProgramId>,
+    // all the known programs, a `ProgramId` is an index in there
+    programs: Vec<Program>,
+    // the id of each name
+    ids: HashMap<String, ProgramId>,
 }
 
 impl fmt::Debug for Tower {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self.root {
-            Some(root) => write!(f, "Tower(r={} p={:?})", root, self.programs.keys()),
+        let names: Vec<&str> = self.programs.iter().map(|p| p.name.as_str()).collect();
+        match self.root_name() {
+            Some(root) => write!(f, "Tower(r={} p={:?})", root, names),
             None => write!(f, "Tower(r=None p=None)"),
         }
     }
 }
 
+impl Index<ProgramId> for Tower {
+    type Output = Program;
+
+    fn index(&self, id: ProgramId) -> &Program {
+        &self.programs[id.0]
+    }
+}
+
+impl IndexMut<ProgramId> for Tower {
+    fn index_mut(&mut self, id: ProgramId) -> &mut Program {
+        &mut self.programs[id.0]
+    }
+}
+
 impl Tower {
     fn new() -> Tower {
         Tower{
             root: None,
-            programs: HashMap::new(),
+            programs: Vec::new(),
+            ids: HashMap::new(),
         }
     }
 
     fn root_name(&self) -> Option<&str> {
-        match &self.root {
-            Some(root) => Some(root),
-            None => None,
+        self.root.map(|root| self[root].name.as_str())
+    }
+
+    fn id(&self, name: &str) -> Option<ProgramId> {
+        self.ids.get(name).cloned()
+    }
+
+    // the id of `name`, a program without weight is created the first time
+    fn intern(&mut self, name: &str) -> ProgramId {
+        if let Some(id) = self.id(name) {
+            return id;
         }
+        let id = ProgramId(self.programs.len());
+        self.programs.push(Program::new(name, None, None));
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    fn program_ids(&self) -> impl Iterator<Item = ProgramId> {
+        (0..self.programs.len()).map(ProgramId)
+    }
+
+    fn names(&self, ids: &[ProgramId]) -> Vec<String> {
+        ids.iter().map(|&id| self[id].name.clone()).collect()
     }
 
     fn from_file(path: &str) -> io::Result<Tower> {
         let file = File::open(path)?;
-        let br = BufReader::new(file);
+        Self::from_reader(BufReader::new(file))
+    }
+
+    /// Lines that do not parse give an `InvalidData` error whose inner error is a `LineError`.
+    fn from_reader<R: BufRead>(reader: R) -> io::Result<Tower> {
         let mut rv = Tower::new();
 
-        for (idx, line) in br.lines().enumerate() {
+        for (idx, line) in reader.lines().enumerate() {
             let line = line?;
-            if let Some(data) = Self::data_from_line(&line) {
-                rv.add(&data.0, data.1, &data.2);
-            } else {
-                // empty line, I guess it's fine
-                eprintln!("No data from line {}", idx);
+            let data = Self::data_from_line(&line)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, LineError{line: idx + 1, error}))?;
+            if let Some(data) = data {
+                rv.add(idx + 1, &data.0, data.1, &data.2);
             }
         }
-        eprintln!("Compute children weight");
-        if rv.root.is_some() {
-            let root = rv.root.clone().unwrap();
-            if let Some(updates) = rv.visit_from(&root) {
-                // consumes elements
-                for (name, children_weight) in updates {
-                    rv.programs.get_mut(&name).unwrap().total_weight = children_weight;
-                    eprintln!("{} => {}", name, children_weight);
+        Ok(rv.with_totals())
+    }
+
+    fn with_totals(mut self) -> Tower {
+        // the weights of a loop never add up, `validate` tells about it
+        if self.cycles().is_empty() {
+            self.update_totals();
+        }
+        self
+    }
+
+    // None if the parents go round in a loop
+    fn find_root_from(&self, id: ProgramId) -> Option<ProgramId> {
+        let mut base = id;
+        // a loop is the only way to go up more than there are programs
+        for _ in 0..=self.programs.len() {
+            match self[base].parent {
+                Some(parent) => base = parent,
+                None => return Some(base),
+            }
+        }
+        None
+    }
+
+    fn lines_of(&self, ids: &[ProgramId]) -> Vec<usize> {
+        let mut rv: Vec<usize> = ids.iter()
+            .flat_map(|&id| self[id].lines.iter().cloned())
+            .collect();
+        rv.sort_unstable();
+        rv.dedup();
+        rv
+    }
+
+    // every program standing on itself through its children, found with a depth first search
+    fn cycles(&self) -> Vec<Vec<ProgramId>> {
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum State { New, Visiting, Done }
+
+        let mut states = vec![State::New; self.programs.len()];
+        let mut rv = Vec::new();
+
+        for start in self.program_ids() {
+            if states[start.0] != State::New {
+                continue;
+            }
+            // the path from `start` with the index of the next child to look at
+            let mut path: Vec<(ProgramId, usize)> = vec![(start, 0)];
+            states[start.0] = State::Visiting;
+            while let Some((id, next)) = path.last_mut() {
+                let id = *id;
+                match self[id].children.get(*next) {
+                    Some(&child) => {
+                        *next += 1;
+                        match states[child.0] {
+                            State::New => {
+                                states[child.0] = State::Visiting;
+                                path.push((child, 0));
+                            },
+                            State::Visiting => {
+                                let from = path.iter().position(|&(p, _)| p == child).unwrap();
+                                rv.push(path[from..].iter().map(|&(p, _)| p).collect());
+                            },
+                            State::Done => {},
+                        }
+                    },
+                    None => {
+                        states[id.0] = State::Done;
+                        path.pop();
+                    },
                 }
             }
         }
-        Ok(rv)
+        rv
     }
 
-    fn find_root_from(&self, name: &str) -> Option<String> {
-        let base = self.programs.get(name)?;
-        match &base.parent {
-            Some(parent) => {
-                println!("  {} <-", name);
-                self.find_root_from(&parent)
-            },
-            None => Some(base.name.clone())
+    // groups of programs linked by parent/child relations, the group of the root first
+    fn components(&self) -> Vec<Vec<ProgramId>> {
+        let mut seen = vec![false; self.programs.len()];
+        let mut rv = Vec::new();
+
+        for start in self.root.into_iter().chain(self.program_ids()) {
+            if seen[start.0] {
+                continue;
+            }
+            seen[start.0] = true;
+            let mut component = Vec::new();
+            let mut todo = vec![start];
+            while let Some(id) = todo.pop() {
+                component.push(id);
+                let program = &self[id];
+                for &other in program.children.iter().chain(program.parent.iter()) {
+                    if !seen[other.0] {
+                        seen[other.0] = true;
+                        todo.push(other);
+                    }
+                }
+            }
+            component.sort();
+            rv.push(component);
         }
+        rv
     }
 
-    // TODO(tr) Recursive and mutable is apparently not working well...
-    // so too bad for the children.
-    /*fn visit_from(&mut self, name: &str) -> Option<u32> {
-        let current = self.programs.get_mut(name)?;
-        let mut sum = 0;
-        println!("visit_from({}) -> {:?}", name, current.children);
-        for child in &current.children {
-            if let Some(value) = self.visit_from(child) {
-                sum += value;
+    /// All that is wrong with the tower, nothing if it is a proper tree.
+    fn validate(&self) -> Vec<Problem> {
+        let mut rv = Vec::new();
+
+        // parents as listed in the children, `parent` only keeps the last one
+        let mut parents: Vec<Vec<ProgramId>> = vec![Vec::new(); self.programs.len()];
+        for id in self.program_ids() {
+            for &child in &self[id].children {
+                parents[child.0].push(id);
             }
         }
-        current.children_weight = sum;
-        Some(sum)
-    }*/
-    fn visit_from(&self, name: &str) -> Option<HashMap<String, u32>> {
-        let mut rv = HashMap::new();
-        let current = self.programs.get(name)?;
-        let mut sum = current.weight.unwrap_or(0);
-        println!("visit_from({}) -> {:?}", name, current.children);
-        for child in &current.children {
-            if let Some(value) = self.visit_from(child) {
-                sum += value[child];
-                rv.extend(value);
+
+        for id in self.program_ids() {
+            let program = &self[id];
+            if program.weight.is_none() {
+                let lines = self.lines_of(&parents[id.0]);
+                rv.push(Problem::Undefined{name: program.name.clone(), lines});
+            }
+            if program.lines.len() > 1 {
+                rv.push(Problem::Duplicate{name: program.name.clone(), lines: program.lines.clone()});
             }
         }
-        rv.insert(name.to_string(), sum);
-        Some(rv)
+        for id in self.program_ids() {
+            let parents = &parents[id.0];
+            if parents.len() > 1 {
+                rv.push(Problem::MultipleParents{
+                    name: self[id].name.clone(),
+                    parents: self.names(parents),
+                    lines: self.lines_of(parents),
+                });
+            }
+        }
+        for ids in self.components().into_iter().skip(1) {
+            rv.push(Problem::Disconnected{programs: self.names(&ids), lines: self.lines_of(&ids)});
+        }
+        for ids in self.cycles() {
+            rv.push(Problem::Cycle{programs: self.names(&ids), lines: self.lines_of(&ids)});
+        }
+        rv
     }
 
-    fn find_unstable_children(&self, name: &str) -> Option<(String, HashMap<String, u32>)> {
-        let current = self.programs.get(name)?;
-        let mut values : HashSet<u32> = HashSet::new();
-        let mut rv = HashMap::new();
-        for child in &current.children {
-            if let Some(child_rv) = self.find_unstable_children(child) {
-                eprintln!("{}->{} is unstable", name, child);
-                return Some(child_rv);
+    /// `id` and the programs above it, each one after its children.
+    /// Keeps its own stack rather than recursing so that any height of tower fits, the programs
+    /// must not loop.
+    fn post_order(&self, id: ProgramId) -> Vec<ProgramId> {
+        let mut rv = Vec::new();
+        let mut stack: Vec<(ProgramId, usize)> = vec![(id, 0)];
+        while let Some((id, next)) = stack.last_mut() {
+            let id = *id;
+            match self[id].children.get(*next) {
+                Some(&child) => {
+                    *next += 1;
+                    stack.push((child, 0));
+                },
+                None => {
+                    rv.push(id);
+                    stack.pop();
+                },
             }
-            let v = self.programs.get(child).unwrap().total_weight;
-            rv.insert(child.clone(), v);
-            values.insert(v);
         }
+        rv
+    }
 
-        if values.len() > 1 {
-            eprintln!("{} is not stable...", name);
-            Some((name.to_string(), rv))
-        } else {
-            eprintln!("{} is stable", name);
-            None
+    /// Compute the total weight of every program from the root, in one pass.
+    fn update_totals(&mut self) {
+        let root = match self.root {
+            Some(root) => root,
+            None => return,
+        };
+        for id in self.post_order(root) {
+            let program = &self[id];
+            let total = program.children.iter().fold(program.weight.unwrap_or(0), |sum, &child| sum + self[child].total_weight);
+            self[id].total_weight = total;
         }
     }
 
+    // the first unstable program in post order, with the total weights of its children
+    fn find_unstable_children(&self, name: &str) -> Option<(String, HashMap<String, u32>)> {
+        let id = self.post_order(self.id(name)?).into_iter().find(|&id| self.is_unbalanced(id))?;
+        let totals = self[id].children.iter()
+            .map(|&child| (self[child].name.clone(), self[child].total_weight))
+            .collect();
+        Some((self[id].name.clone(), totals))
+    }
+
+    // `find_correction` tells which weight to change
     fn search_unstable(&self) -> Option<(String, HashMap<String, u32>)> {
-        // TODO(tr) We could use the result to know which one of the children is bad
-        //  and compute bad_child.weight -= diff to return the value bad_child should
-        //  have for the tower to be balanced.
-        //  At the moment I computed that by hand searching for the bad entry in the
-        //  input which is not great...
-        self.find_unstable_children(self.root_name().unwrap_or(&""))
-    }
-
-    fn add(&mut self, name: &str, weight: u32, children: &[String]) {
-        // let mut parent = self.programs.entry(name.to_string())
-        //     .or_insert(Program::new(name));
-
-        println!("Adding {} ({}) {:?}",
-                 name,
-                 weight,
-                 children,
-        );
-        // let parent = self.programs.get_mut(name).unwrap();
-        // parent.weight = Some(weight);
-        if let Some(parent) = self.programs.get_mut(name) {
-            // println!("  {} is known", name);
-            parent.weight = Some(weight);
-        } else {
-            let entry = Program::new(name, Some(weight), None);
-            // println!("  {} is new", name);
-            // entry.weight = Some(weight);
-            self.programs.insert(name.to_string(), entry);
-        }
-
-        // let parent = self.programs.get_mut(name).unwrap();
-        for child in children {
-            // if self.root.is_some() && self.root.as_ref().unwrap() == child {
-            //     // change the root to the current entry!
-            //     println!("find root from {}", name);
-            //     let new_root = self.find_root_from(name).unwrap();
-            //     if new_root == name {
-            //         println!("  new root is {}", name);
-            //     } else {
-            //         println!("  new root is {} (root from {})", new_root, name);
-            //     }
-            //     self.root = Some(new_root);
-            // }
-
-            // self.programs.entry(child.to_string())
-            //     .or_insert(
-            //         Program::new(child, None, Some(name.to_string()))
-            //     );
-            match self.programs.get_mut(child) {
-                Some(child) => {
-                    println!("  updating {} parent -> {}", &child.name, name);
-                    child.parent = Some(name.to_string());
+        self.find_unstable_children(self.root_name().unwrap_or(""))
+    }
+
+    fn is_unbalanced(&self, id: ProgramId) -> bool {
+        let mut totals = self[id].children.iter().map(|&child| self[child].total_weight);
+        match totals.next() {
+            Some(first) => totals.any(|total| total != first),
+            None => false,
+        }
+    }
+
+    fn correction(&self, parent: ProgramId, id: ProgramId, delta: i64) -> Result<Correction, CorrectionError> {
+        let weight = self[id].weight.unwrap_or(0);
+        let expected = i64::from(weight) - delta;
+        if expected < 0 || expected > i64::from(u32::MAX) {
+            return Err(CorrectionError::Impossible);
+        }
+        Ok(Correction{
+            name: self[id].name.clone(),
+            weight,
+            expected: expected as u32,
+            siblings: self[parent].children.iter()
+                .map(|&child| (self[child].name.clone(), self[child].total_weight))
+                .collect(),
+        })
+    }
+
+    /// The one weight to change for the tower to be balanced, None if it already is.
+    ///
+    /// Goes down from the root towards the unbalanced programs. Under an unbalanced program the
+    /// child with the wrong total is the odd one out, or the one leading to more unbalanced
+    /// programs. With exactly two children and nothing below, the difference found higher up
+    /// (how much too heavy the branch is) tells which one it is.
+    /// The totals must be up to date, on a tower that `validate` finds nothing wrong with.
+    fn find_correction(&self) -> Result<Option<Correction>, CorrectionError> {
+        // the unbalanced programs and all their ancestors
+        let mut troubled = vec![false; self.programs.len()];
+        for id in self.program_ids().filter(|&id| self.is_unbalanced(id)) {
+            let mut current = Some(id);
+            while let Some(id) = current {
+                if troubled[id.0] {
+                    break;
+                }
+                troubled[id.0] = true;
+                current = self[id].parent;
+            }
+        }
+        let mut current = match self.root {
+            Some(root) if troubled[root.0] => root,
+            _ => return Ok(None),
+        };
+        // how much heavier than it should be the branch we are in is, once known
+        let mut delta: Option<i64> = None;
+
+        loop {
+            let total = |id: ProgramId| i64::from(self[id].total_weight);
+            let children = &self[current].children;
+            let mut below = children.iter().cloned().filter(|child| troubled[child.0]);
+            let next = below.next();
+            if below.next().is_some() {
+                // more than one branch is wrong
+                return Err(CorrectionError::Impossible);
+            }
+            if !self.is_unbalanced(current) {
+                if children.len() > 1 {
+                    // the branch weighs the same as its siblings, changing one weight in it would not
+                    return Err(CorrectionError::Impossible);
+                }
+                current = next.unwrap();
+                continue;
+            }
+
+            // the child with the wrong total
+            let odd = match next {
+                Some(next) => Some(next),
+                None if children.len() > 2 => children.iter().cloned()
+                    .find(|&child| children.iter().filter(|&&other| total(other) == total(child)).count() == 1),
+                None => None,
+            };
+            match odd {
+                Some(odd) => {
+                    let others: Vec<i64> = children.iter().cloned().filter(|&c| c != odd).map(total).collect();
+                    if others.iter().any(|&t| t != others[0]) {
+                        return Err(CorrectionError::Impossible);
+                    }
+                    let here = total(odd) - others[0];
+                    if delta.is_some_and(|delta| delta != here) {
+                        return Err(CorrectionError::Impossible);
+                    }
+                    if next.is_none() {
+                        return self.correction(current, odd, here).map(Some);
+                    }
+                    delta = Some(here);
+                    current = odd;
                 },
-                None => {
-                    self.programs.insert(
-                        child.to_string(),
-                        Program::new(child, None, Some(name.to_string())),
-                    );
+                None if children.len() == 2 => {
+                    let (a, b) = (children[0], children[1]);
+                    let here = total(a) - total(b);
+                    return match delta {
+                        Some(delta) if delta == here => self.correction(current, a, here).map(Some),
+                        Some(delta) if delta == -here => self.correction(current, b, -here).map(Some),
+                        Some(_) => Err(CorrectionError::Impossible),
+                        None => {
+                            let candidates = vec!(self.correction(current, a, here), self.correction(current, b, -here));
+                            let candidates: Vec<Correction> = candidates.into_iter().filter_map(Result::ok).collect();
+                            match candidates.len() {
+                                0 => Err(CorrectionError::Impossible),
+                                1 => Ok(candidates.into_iter().next()),
+                                _ => Err(CorrectionError::Ambiguous(candidates)),
+                            }
+                        },
+                    };
+                },
+                None => return Err(CorrectionError::Impossible),
+            }
+        }
+    }
+
+    fn add(&mut self, line: usize, name: &str, weight: u32, children: &[String]) {
+        let id = self.intern(name);
+        self[id].weight = Some(weight);
+        self[id].lines.push(line);
+
+        let children: Vec<ProgramId> = children.iter().map(|child| self.intern(child)).collect();
+        for &child in &children {
+            self[child].parent = Some(id);
+            self[id].children.push(child);
+        }
+
+        match self.root {
+            None => self.root = Some(id),
+            Some(root) if children.contains(&root) => {
+                // no new root when the programs loop, `validate` tells about it
+                if let Some(new_root) = self.find_root_from(id) {
+                    self.root = Some(new_root);
+                }
+            },
+            Some(_) => {},
+        }
+    }
+
+    fn known(&self, name: &str) -> Result<ProgramId, EditError> {
+        self.id(name).ok_or_else(|| EditError::Unknown(name.to_string()))
+    }
+
+    // Add `delta` to the total weight of `id` and everything below it. Nothing changes if
+    // one of the totals would overflow.
+    fn add_to_totals(&mut self, id: ProgramId, delta: i64) -> Result<(), EditError> {
+        let mut totals = Vec::new();
+        let mut current = Some(id);
+        while let Some(id) = current {
+            let total = u32::try_from(i64::from(self[id].total_weight) + delta)
+                .map_err(|_| EditError::Overflow(self[id].name.clone()))?;
+            totals.push((id, total));
+            current = self[id].parent;
+        }
+        for (id, total) in totals {
+            self[id].total_weight = total;
+        }
+        Ok(())
+    }
+
+    // The editing methods keep the totals up to date by changing only the programs below the
+    // edit. They expect a tower that `validate` finds nothing wrong with and keep it that way.
+
+    fn set_weight(&mut self, name: &str, weight: u32) -> Result<(), EditError> {
+        let id = self.known(name)?;
+        let old = self[id].weight.unwrap_or(0);
+        self.add_to_totals(id, i64::from(weight) - i64::from(old))?;
+        self[id].weight = Some(weight);
+        Ok(())
+    }
+
+    /// Add a program standing on `parent`, or at the bottom of the tower with the old root
+    /// standing on it.
+    fn add_program(&mut self, name: &str, weight: u32, parent: Option<&str>) -> Result<(), EditError> {
+        if self.id(name).is_some() {
+            return Err(EditError::Exists(name.to_string()));
+        }
+        let parent = parent.map(|parent| self.known(parent)).transpose()?;
+        match parent {
+            Some(parent) => self.add_to_totals(parent, i64::from(weight))?,
+            None => {
+                let below = self.root.map_or(0, |root| self[root].total_weight);
+                if below.checked_add(weight).is_none() {
+                    return Err(EditError::Overflow(name.to_string()));
+                }
+            },
+        }
+        let id = self.intern(name);
+        self[id].weight = Some(weight);
+        self[id].total_weight = weight;
+
+        match parent {
+            Some(parent) => {
+                self[id].parent = Some(parent);
+                self[parent].children.push(id);
+            },
+            None => {
+                if let Some(root) = self.root {
+                    self[root].parent = Some(id);
+                    self[id].children.push(root);
+                    self[id].total_weight += self[root].total_weight;
                 }
+                self.root = Some(id);
+            },
+        }
+        Ok(())
+    }
+
+    /// Remove a program and all the programs above it.
+    /// Other programs keep their ids, but for the last ones which move into the freed slots.
+    fn remove_program(&mut self, name: &str) -> Result<(), EditError> {
+        let id = self.known(name)?;
+        if let Some(parent) = self[id].parent {
+            self.add_to_totals(parent, -i64::from(self[id].total_weight))?;
+            self[parent].children.retain(|&child| child != id);
+        }
+        if self.root == Some(id) {
+            self.root = None;
+        }
+
+        let mut removed = self.post_order(id);
+        // from the highest id, the last program is then never one still to be removed
+        removed.sort_unstable_by(|a, b| b.cmp(a));
+        for gone in removed {
+            let program = self.programs.swap_remove(gone.0);
+            self.ids.remove(&program.name);
+            if gone.0 < self.programs.len() {
+                self.renumber(ProgramId(self.programs.len()), gone);
             }
+        }
+        Ok(())
+    }
 
-            // parent.children.push(child.to_string());
-            // sad that we have to get it again every time.
-            self.programs.get_mut(name).unwrap().children.push(child.to_string());
-            // self.programs[name].children.push(child.clone()); // [] is not mutable...
-        }
-
-        println!("Checking if {:?} is still the root...", self.root);
-        if self.root.is_none() {
-            println!("  no root, new root is {}", name);
-            self.root = Some(name.to_string());
-        } else if children.contains(&self.root.as_ref().unwrap()) {
-            println!("find root from {}", name);
-            let new_root = self.find_root_from(name).unwrap();
-            if new_root == name {
-                println!("  new root is {}", name);
-            } else {
-                println!("  new root is {} (root from {})", new_root, name);
+    // the program at `old` now is at `new`, point everything there
+    fn renumber(&mut self, old: ProgramId, new: ProgramId) {
+        let moved = |other: &mut ProgramId| if *other == old { *other = new };
+        if let Some(parent) = self[new].parent {
+            self[parent].children.iter_mut().for_each(moved);
+        }
+        for child in self[new].children.clone() {
+            self[child].parent = Some(new);
+        }
+        self.root.iter_mut().for_each(moved);
+        self.ids.insert(self[new].name.clone(), new);
+    }
+
+    /// Move a program, with all the programs above it, to stand on `parent` as its last child.
+    fn reparent(&mut self, name: &str, parent: &str) -> Result<(), EditError> {
+        let id = self.known(name)?;
+        let new_parent = self.known(parent)?;
+        let mut current = Some(new_parent);
+        while let Some(below) = current {
+            if below == id {
+                return Err(EditError::Loop{name: name.to_string(), parent: parent.to_string()});
             }
-            self.root = Some(new_root);
-        }
-    }
-
-    // returns a tuple to simplify testing
-    fn data_from_line(line: &str) -> Option<(String, u32, Vec<String>)> {
-        // match lines like "{prgm} ({weight}) -> {prgm}"
-        // match lines like "{prgm} ({weight}) -> {prgm},{prgm}"
-        // or lines like "{prgm} ({weight})"
-
-        let actions: Vec<&str> = line.split_whitespace().collect();
-        // why is that a &&str??? or is pycharm confusing me...
-        let name = actions.get(0).expect("no name");
-        let weight:u32 = actions.get(1).expect("no weight_str")
-            .replace("(", "")
-            .replace(")", "")
-            .parse().expect("invalid weight");
-        // now check if we have children
-        let mut children = Vec::new();
-        let actions: Vec<&str> = line.split(" -> ").collect();
-
-        if let Some(children_string) = actions.get(1) {
-            for child_name in children_string.replace(" ", "").split(",") {
-                children.push(child_name.to_string());
+            current = self[below].parent;
+        }
+        if self[id].parent == Some(new_parent) {
+            return Ok(());
+        }
+
+        let total = i64::from(self[id].total_weight);
+        if let Some(old) = self[id].parent {
+            self.add_to_totals(old, -total)?;
+            self[old].children.retain(|&child| child != id);
+        }
+        // cannot overflow, the new totals are all part of the one of the root which stays the same
+        self.add_to_totals(new_parent, total)?;
+        self[id].parent = Some(new_parent);
+        self[new_parent].children.push(id);
+        Ok(())
+    }
+
+    fn apply(&mut self, edit: &Edit) -> Result<(), EditError> {
+        match edit {
+            Edit::SetWeight { name, weight } => self.set_weight(name, *weight),
+            Edit::Add { name, weight, parent } => self.add_program(name, *weight, parent.as_deref()),
+            Edit::Remove { name } => self.remove_program(name),
+            Edit::Move { name, parent } => self.reparent(name, parent),
+        }
+    }
+
+    /// Whether the children of every program weigh the same.
+    fn is_balanced(&self) -> bool {
+        !self.program_ids().any(|id| self.is_unbalanced(id))
+    }
+
+    fn label(&self, id: ProgramId) -> String {
+        let program = &self[id];
+        let weight = program.weight.map_or("?".to_string(), |w| w.to_string());
+        format!("{} ({}) {}", program.name, weight, program.total_weight)
+    }
+
+    /// A Graphviz graph of the tower, from each program to its children.
+    /// Programs whose children do not weigh the same are red, the one to correct is filled.
+    fn to_dot(&self) -> String {
+        let wrong = match self.find_correction() {
+            Ok(Some(correction)) => self.id(&correction.name),
+            _ => None,
+        };
+        let quote = |name: &str| format!("\"{}\"", name.escape_debug());
+        let mut rv = String::from("digraph tower {\n    node [shape=box];\n");
+        for id in self.program_ids() {
+            let program = &self[id];
+            let weight = program.weight.map_or("?".to_string(), |w| w.to_string());
+            let mut attributes = format!("label=\"{}\\nweight {}\\ntotal {}\"",
+                                         program.name.escape_debug(), weight, program.total_weight);
+            if self.is_unbalanced(id) {
+                attributes.push_str(", color=red");
+            }
+            if wrong == Some(id) {
+                attributes.push_str(", style=filled, fillcolor=orange");
+            }
+            rv.push_str(&format!("    {} [{}];\n", quote(&program.name), attributes));
+        }
+        for id in self.program_ids() {
+            for &child in &self[id].children {
+                rv.push_str(&format!("    {} -> {};\n", quote(&self[id].name), quote(&self[child].name)));
+            }
+        }
+        rv.push_str("}\n");
+        rv
+    }
+
+    /// The tower from its root, one program per line as `name (weight) total` with its children
+    /// indented under it, down to `max_depth` levels above the root if given.
+    fn to_tree(&self, max_depth: Option<usize>) -> String {
+        let mut rv = String::new();
+        // the programs still to print with their depth, the indentation and the branch to them
+        let mut stack: Vec<(ProgramId, usize, String, &str)> = match self.root {
+            Some(root) => vec![(root, 0, String::new(), "")],
+            None => return rv,
+        };
+        while let Some((id, depth, indent, branch)) = stack.pop() {
+            rv.push_str(&format!("{}{}{}", indent, branch, self.label(id)));
+            if self.is_unbalanced(id) {
+                rv.push_str(" unbalanced");
+            }
+            let children = &self[id].children;
+            if max_depth.is_some_and(|max| depth >= max) && !children.is_empty() {
+                rv.push_str(&format!(" [{} children hidden]", children.len()));
+                rv.push('\n');
+                continue;
+            }
+            rv.push('\n');
+
+            let indent = match branch {
+                "" => indent,
+                "`-- " => indent + "    ",
+                _ => indent + "|   ",
+            };
+            // pushed last to first to be printed in order
+            for (i, &child) in children.iter().enumerate().rev() {
+                let branch = if i + 1 == children.len() { "`-- " } else { "|-- " };
+                stack.push((child, depth + 1, indent.clone(), branch));
             }
         }
+        rv
+    }
+
+    /// The programs connected to the root in the format of the puzzle input, the root first
+    /// and then each program before the programs above it, children in order.
+    /// Programs that are used but never defined do not get a line, like in the input.
+    fn to_text(&self) -> String {
+        let mut rv = String::new();
+        let mut stack: Vec<ProgramId> = self.root.into_iter().collect();
+        while let Some(id) = stack.pop() {
+            let program = &self[id];
+            stack.extend(program.children.iter().rev());
+            let weight = match program.weight {
+                Some(weight) => weight,
+                None => continue,
+            };
+            rv.push_str(&format!("{} ({})", program.name, weight));
+            if !program.children.is_empty() {
+                rv.push_str(&format!(" -> {}", self.names(&program.children).join(", ")));
+            }
+            rv.push('\n');
+        }
+        rv
+    }
 
-        Some((name.to_string(), weight, children))
+    // returns a tuple to simplify testing, None for blank lines and comments
+    fn data_from_line(line: &str) -> Result<Option<(String, u32, Vec<String>)>, ParseError> {
+        Ok(parse::parse_line(line)?.map(|e| (e.name, e.weight, e.children)))
     }
 
 }
 
+
+/// A change to the tower, written as one of
+/// `set NAME WEIGHT`, `add NAME WEIGHT [PARENT]`, `remove NAME` or `move NAME PARENT`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Edit {
+    SetWeight { name: String, weight: u32 },
+    /// without parent the program goes at the bottom of the tower
+    Add { name: String, weight: u32, parent: Option<String> },
+    Remove { name: String },
+    Move { name: String, parent: String },
+}
+
+impl Edit {
+    fn parse(line: &str) -> Result<Edit, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let weight = |word: &str| word.parse::<u32>().map_err(|_| format!("invalid weight {}", word));
+        match words.as_slice() {
+            ["set", name, w] => Ok(Edit::SetWeight{name: name.to_string(), weight: weight(w)?}),
+            ["add", name, w] => Ok(Edit::Add{name: name.to_string(), weight: weight(w)?, parent: None}),
+            ["add", name, w, parent] => Ok(Edit::Add{
+                name: name.to_string(),
+                weight: weight(w)?,
+                parent: Some(parent.to_string()),
+            }),
+            ["remove", name] => Ok(Edit::Remove{name: name.to_string()}),
+            ["move", name, parent] => Ok(Edit::Move{name: name.to_string(), parent: parent.to_string()}),
+            _ => Err(format!("unknown edit '{}'", line.trim())),
+        }
+    }
+}
+
+struct Options {
+    dot: bool,
+    tree: bool,
+    depth: Option<usize>,
+    edit: bool,
+    write: Option<Format>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+}
+
+// usage: day_07 PATH [--dot | --tree [--depth N] | --edit | --write text|json]
+// PATH is read as JSON when it ends with .json
+// --edit reads edits from the standard input, one per line, and tells how balanced the tower is
+// after each of them
+impl Options {
+    fn from_args(args: &[String]) -> Result<Options, String> {
+        let mut rv = Options{
+            dot: false,
+            tree: false,
+            depth: None,
+            edit: false,
+            write: None,
+        };
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--dot" => rv.dot = true,
+                "--tree" => rv.tree = true,
+                "--edit" => rv.edit = true,
+                "--write" => rv.write = Some(match value()?.as_str() {
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    other => return Err(format!("unknown format {}", other)),
+                }),
+                "--depth" => rv.depth = Some(value()?.parse().map_err(|_| "--depth needs a number")?),
+                other => return Err(format!("unknown argument {}", other)),
+            }
+        }
+        if [rv.dot, rv.tree, rv.edit, rv.write.is_some()].iter().filter(|&&o| o).count() > 1 {
+            return Err("only one of --dot, --tree, --edit and --write can be used".to_string());
+        }
+        if rv.depth.is_some() && !rv.tree {
+            return Err("--depth needs --tree".to_string());
+        }
+        Ok(rv)
+    }
+}
+
+struct GenerateOptions {
+    fault: Option<usize>,
+    generator: Generator,
+}
+
+// usage: day_07 --generate [--depth N] [--branching N|MIN-MAX] [--weights N|MIN-MAX] [--seed N]
+//                          [--fault DEPTH]
+// writes the tower to the standard output and its root, with the weight to correct if there
+// is a --fault, to the standard error
+impl GenerateOptions {
+    fn from_args(args: &[String]) -> Result<GenerateOptions, String> {
+        let mut depth = 3;
+        let mut branching = 2..=4;
+        let mut weights = 1..=100;
+        let mut seed = 2017;
+        let mut fault = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--generate" => {},
+                "--depth" => depth = value()?.parse().map_err(|_| "--depth needs a number")?,
+                "--branching" => branching = parse_range(value()?)?,
+                "--weights" => weights = parse_range(value()?)?,
+                "--seed" => seed = value()?.parse().map_err(|_| "--seed needs a number")?,
+                "--fault" => fault = Some(value()?.parse().map_err(|_| "--fault needs a number")?),
+                other => return Err(format!("unknown argument {}", other)),
+            }
+        }
+        match fault {
+            Some(0) => return Err("the root cannot have a wrong weight, it has no siblings".to_string()),
+            Some(fault) if fault > depth => return Err(format!("--fault {} is above the top of the tower", fault)),
+            _ => {},
+        }
+        Ok(GenerateOptions{
+            fault,
+            generator: Generator::new(depth, branching, weights, seed),
+        })
+    }
+}
+
+fn generate(args: &[String]) {
+    let mut options = match GenerateOptions::from_args(args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("invalid arguments: {}", e);
+            exit(1);
+        },
+    };
+    let generated = match options.generator.generate(options.fault) {
+        Some(g) => g,
+        None => {
+            eprintln!("could not generate a tower with these options");
+            exit(1);
+        },
+    };
+    print!("{}", generated.text);
+    eprintln!("Root of the tower is {}", generated.root);
+    if let Some(c) = generated.correction {
+        eprintln!("{} weighs {} but should weigh {} (totals: {:?})", c.name, c.weight, c.expected, c.siblings);
+    }
+}
+
+fn print_correction(tower: &Tower) {
+    match tower.find_correction() {
+        Ok(Some(c)) => println!("{} weighs {} but should weigh {} (totals: {:?})", c.name, c.weight, c.expected, c.siblings),
+        Ok(None) => {},
+        Err(CorrectionError::Ambiguous(candidates)) => {
+            println!("Could be any of:");
+            for c in candidates {
+                println!("  {} weighs {} but should weigh {} (totals: {:?})", c.name, c.weight, c.expected, c.siblings);
+            }
+        },
+        Err(CorrectionError::Impossible) => println!("Changing one weight cannot balance the tower"),
+    }
+}
+
 fn main() {
     let path = env::args().nth(1).expect("please supply a path");
-    let tower = match Tower::from_file(&path) {
+    if path == "--generate" {
+        let args: Vec<String> = env::args().skip(1).collect();
+        generate(&args);
+        return;
+    }
+    let args: Vec<String> = env::args().skip(2).collect();
+    let options = match Options::from_args(&args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("invalid arguments: {}", e);
+            exit(1);
+        },
+    };
+    let tower = if path.ends_with(".json") {
+        fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|text| json::from_json(&text))
+    } else {
+        Tower::from_file(&path).map_err(|e| e.to_string())
+    };
+    let mut tower = match tower {
         Ok(t) => t,
         Err(e) => {
             eprintln!("failed: {}", e);
@@ -275,6 +987,46 @@ fn main() {
         },
     };
 
+    let problems = tower.validate();
+    if !problems.is_empty() {
+        for problem in problems {
+            eprintln!("{}", problem);
+        }
+        exit(1);
+    }
+    if options.dot {
+        print!("{}", tower.to_dot());
+        return;
+    }
+    if options.tree {
+        print!("{}", tower.to_tree(options.depth));
+        return;
+    }
+    if let Some(format) = options.write {
+        match format {
+            Format::Text => print!("{}", tower.to_text()),
+            Format::Json => print!("{}", json::to_json(&tower)),
+        }
+        return;
+    }
+    if options.edit {
+        for line in io::stdin().lock().lines() {
+            let line = line.expect("cannot read the edits");
+            if line.trim().is_empty() {
+                continue;
+            }
+            match Edit::parse(&line).and_then(|edit| tower.apply(&edit).map_err(|e| e.to_string())) {
+                Err(e) => eprintln!("{}", e),
+                Ok(()) if tower.is_balanced() => match tower.root {
+                    Some(root) => println!("Tower is stable, {} carries {}", tower[root].name, tower[root].total_weight),
+                    None => println!("Tower is empty"),
+                },
+                Ok(()) => print_correction(&tower),
+            }
+        }
+        return;
+    }
+
     println!("Root of the tower is {:?}", tower.root_name());
 
     if let Some(unbalanced) = tower.search_unstable() {
@@ -282,11 +1034,14 @@ fn main() {
     } else {
         println!("Tower is stable");
     }
+    print_correction(&tower);
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use random::Random;
+    use std::error::Error;
     use rstest::rstest;
 
     fn some_data(name: &str, weight: u32, children: Vec<&'static str>) -> Option<(String, u32, Vec<String>)> {
@@ -301,9 +1056,32 @@ mod test {
     case(&"pbga (66)", some_data("pbga", 66, Vec::new())),
     case(&"fwft (72) -> ktlj", some_data("fwft", 72, vec!["ktlj"])),
     case(&"fwft (72) -> ktlj, cntj, xhth", some_data("fwft", 72, vec!["ktlj", "cntj", "xhth"])),
+    case(&"fwft(72)->ktlj,\tcntj", some_data("fwft", 72, vec!["ktlj", "cntj"])),
+    case(&"", None),
+    case(&"# fwft (72)", None),
     )]
     fn test_data_from_line(line: &str, exp_data: Option<(String, u32, Vec<String>)>) {
-        assert_eq!(Tower::data_from_line(line), exp_data);
+        assert_eq!(Tower::data_from_line(line), Ok(exp_data));
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let error = Tower::from_reader("a (1) -> b\n\n# b is next\nb (2\n".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "line 4, column 5: expected ')' but the line ended");
+
+        let inner = error.get_ref().unwrap().downcast_ref::<LineError>().unwrap();
+        let kind = parse::ErrorKind::Expected { expected: parse::Token::CloseParen, found: None };
+        assert_eq!(*inner, LineError{line: 4, error: ParseError{column: 5, kind}});
+        assert_eq!(inner.source().unwrap().to_string(), "column 5: expected ')' but the line ended");
+    }
+
+    #[test]
+    fn test_load_comments() {
+        let tower = tower("# root first\n\na (1) -> b, c # two children\n  \nb (2)\nc (2)\n");
+        assert_eq!(tower.validate(), Vec::new());
+        assert_eq!(tower[tower.id("a").unwrap()].total_weight, 5);
+        assert_eq!(tower[tower.id("c").unwrap()].lines, vec!(6));
     }
 
     #[rstest(path, root,
@@ -313,8 +1091,7 @@ mod test {
     fn test_load_file(path: &str, root: &str) {
         let tower = Tower::from_file(path).unwrap();
         println!("{:?}", tower);
-        assert!(tower.root.is_some());
-        assert_eq!(tower.root.unwrap(), root);
+        assert_eq!(tower.root_name(), Some(root));
     }
 
     #[rstest(name, total_weight, unbalanced,
@@ -324,16 +1101,482 @@ mod test {
     case(&"root", 20 + 41 + 251 + 243 + 243, Some("tknk".to_string())),
     )]
     fn test_find_balanced(name: &str, total_weight: u32, unbalanced: Option<String>) {
-        let tower = Tower::from_file(&"day_07/test_3.txt").unwrap();
-        let program = tower.programs.get(name).unwrap();
+        let tower = Tower::from_file("day_07/test_3.txt").unwrap();
+        let program = &tower[tower.id(name).unwrap()];
         println!("Program: {:?}", program);
         assert_eq!(program.total_weight, total_weight);
         let rv = tower.find_unstable_children(name);
-        if unbalanced.is_some() {
+        if let Some(unbalanced) = unbalanced {
             assert!(rv.is_some());
-            assert_eq!(rv.unwrap().0, unbalanced.unwrap());
+            assert_eq!(rv.unwrap().0, unbalanced);
         } else {
             assert!(rv.is_none());
         }
     }
+
+    fn tower(text: &str) -> Tower {
+        Tower::from_reader(text.as_bytes()).unwrap()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[rstest(path,
+    case(&"day_07/test.txt"),
+    case(&"day_07/test_2.txt"),
+    case(&"day_07/test_3.txt"),
+    case(&"day_07/input.txt"),
+    )]
+    fn test_validate_valid(path: &str) {
+        assert_eq!(Tower::from_file(path).unwrap().validate(), Vec::new());
+    }
+
+    #[rstest(text, expected,
+    case("a (1) -> b\nc (2)", vec!(
+        Problem::Undefined{name: "b".to_string(), lines: vec!(1)},
+        Problem::Disconnected{programs: names(&["c"]), lines: vec!(2)},
+    )),
+    case("a (1) -> b\nb (2)\nb (3)", vec!(
+        Problem::Duplicate{name: "b".to_string(), lines: vec!(2, 3)},
+    )),
+    case("a (1) -> b, c\nb (1) -> c\nc (1)", vec!(
+        Problem::MultipleParents{name: "c".to_string(), parents: names(&["a", "b"]), lines: vec!(1, 2)},
+    )),
+    case("a (1) -> b\nb (1) -> c\nc (1) -> a", vec!(
+        Problem::Cycle{programs: names(&["a", "b", "c"]), lines: vec!(1, 2, 3)},
+    )),
+    case("root (1) -> x\nx (1)\na (1) -> b\nb (1) -> a", vec!(
+        Problem::Disconnected{programs: names(&["a", "b"]), lines: vec!(3, 4)},
+        Problem::Cycle{programs: names(&["a", "b"]), lines: vec!(3, 4)},
+    )),
+    case("a (1) -> a", vec!(
+        Problem::Cycle{programs: names(&["a"]), lines: vec!(1)},
+    )),
+    )]
+    fn test_validate(text: &str, expected: Vec<Problem>) {
+        assert_eq!(tower(text).validate(), expected);
+    }
+
+    #[rstest(problem, expected,
+    case(Problem::Undefined{name: "b".to_string(), lines: vec!(1, 4)}, "line 1, 4: b is used but never defined"),
+    case(Problem::Cycle{programs: names(&["a", "b", "c"]), lines: vec!(1, 2, 3)},
+         "line 1, 2, 3: a -> b -> c stand on each other"),
+    )]
+    fn test_problem_display(problem: Problem, expected: &str) {
+        assert_eq!(problem.to_string(), expected);
+    }
+
+    fn correction(name: &str, weight: u32, expected: u32, siblings: &[(&str, u32)]) -> Correction {
+        Correction{
+            name: name.to_string(),
+            weight,
+            expected,
+            siblings: siblings.iter().map(|&(n, t)| (n.to_string(), t)).collect(),
+        }
+    }
+
+    #[rstest(path, expected,
+    case(&"day_07/test.txt", Some(correction("ugml", 68, 60, &[("ugml", 251), ("padx", 243), ("fwft", 243)]))),
+    case(&"day_07/test_3.txt", Some(correction("ugml", 68, 60, &[("ugml", 251), ("padx", 243), ("fwft", 243)]))),
+    case(&"day_07/test_2.txt", None),
+    )]
+    fn test_find_correction_file(path: &str, expected: Option<Correction>) {
+        assert_eq!(Tower::from_file(path).unwrap().find_correction(), Ok(expected));
+    }
+
+    #[test]
+    fn test_find_correction_input() {
+        let tower = Tower::from_file("day_07/input.txt").unwrap();
+        let rv = tower.find_correction().unwrap().unwrap();
+        assert_eq!((rv.name.as_str(), rv.weight, rv.expected), ("kiatxq", 1232, 1226));
+    }
+
+    #[rstest(text, expected,
+    // two children and nothing else to tell which one is wrong
+    case("a (1) -> b, c\nb (2)\nc (3)", Err(CorrectionError::Ambiguous(vec!(
+        correction("b", 2, 3, &[("b", 2), ("c", 3)]),
+        correction("c", 3, 2, &[("b", 2), ("c", 3)]),
+    )))),
+    // x should weigh 7 like its siblings
+    case("r (1) -> x, y, z\nx (1) -> a, b\na (2)\nb (3)\ny (7)\nz (7)",
+         Ok(Some(correction("a", 2, 3, &[("a", 2), ("b", 3)])))),
+    case("r (1) -> x, y, z\nx (1) -> a, b\na (2)\nb (3)\ny (5)\nz (5)",
+         Ok(Some(correction("b", 3, 2, &[("a", 2), ("b", 3)])))),
+    // the same with only two children at each level
+    case("r (1) -> x, y\nx (1) -> a, b\na (2)\nb (3)\ny (7)",
+         Ok(Some(correction("a", 2, 3, &[("a", 2), ("b", 3)])))),
+    // through a single child, which tells nothing
+    case("r (1) -> s\ns (1) -> x, y, z\nx (1) -> t\nt (1) -> a, b\na (2)\nb (3)\ny (8)\nz (8)",
+         Ok(Some(correction("a", 2, 3, &[("a", 2), ("b", 3)])))),
+    // x weighs the same as its siblings
+    case("r (1) -> x, y, z\nx (1) -> a, b\na (2)\nb (3)\ny (6)\nz (6)", Err(CorrectionError::Impossible)),
+    // two branches are wrong
+    case("r (1) -> x, y, z\nx (1) -> a, b\na (2)\nb (3)\ny (1) -> c, d\nc (2)\nd (3)\nz (6)",
+         Err(CorrectionError::Impossible)),
+    case("r (1) -> a, b, c\na (1)\nb (2)\nc (3)", Err(CorrectionError::Impossible)),
+    // b would need a negative weight
+    case("r (1) -> b, c, d\nb (0) -> e\ne (10)\nc (1)\nd (1)", Err(CorrectionError::Impossible)),
+    case("r (1) -> b, c, d\nb (1)\nc (1)\nd (1)", Ok(None)),
+    case("r (1)", Ok(None)),
+    )]
+    fn test_find_correction(text: &str, expected: Result<Option<Correction>, CorrectionError>) {
+        assert_eq!(tower(text).find_correction(), expected);
+    }
+
+    #[test]
+    fn test_deep_chain() {
+        // far too high to recurse through
+        let count = 1_000_000;
+        let mut lines: Vec<String> = (0..count - 1).map(|i| format!("p{} (1) -> p{}", i, i + 1)).collect();
+        lines.push(format!("p{} (2)", count - 1));
+        let tower = tower(&lines.join("\n"));
+
+        assert_eq!(tower.root_name(), Some("p0"));
+        let root = tower.id("p0").unwrap();
+        assert_eq!(tower[root].total_weight, count + 1);
+        assert_eq!(tower[tower.id("p999999").unwrap()].total_weight, 2);
+        assert_eq!(tower.find_unstable_children("p0"), None);
+        assert_eq!(tower.post_order(root).len(), count as usize);
+    }
+
+    #[test]
+    fn test_deep_chain_bottom_up() {
+        // each program becomes the root in turn
+        let count = 10_000;
+        let mut lines: Vec<String> = (0..count - 1).map(|i| format!("p{} (1) -> p{}", i, i + 1)).collect();
+        lines.push(format!("p{} (2)", count - 1));
+        lines.reverse();
+        let tower = tower(&lines.join("\n"));
+
+        assert_eq!(tower.root_name(), Some("p0"));
+        assert_eq!(tower[tower.id("p0").unwrap()].total_weight, count + 1);
+        assert_eq!(tower.validate(), Vec::new());
+        assert_eq!(tower.find_correction(), Ok(None));
+    }
+
+    #[test]
+    fn test_program_ids() {
+        let mut tower = Tower::from_file("day_07/test.txt").unwrap();
+        let ugml = tower.id("ugml").unwrap();
+        assert_eq!(tower[ugml].name, "ugml");
+        assert_eq!(tower.intern("ugml"), ugml);
+        assert_eq!(tower.id("nope"), None);
+        let parent = tower[ugml].parent.unwrap();
+        assert_eq!(tower[parent].name, "tknk");
+        assert!(tower[parent].children.contains(&ugml));
+
+        // fix the weight in place and count again
+        tower[ugml].weight = Some(60);
+        tower.update_totals();
+        assert_eq!(tower[ugml].total_weight, 243);
+        assert_eq!(tower[parent].total_weight, 41 + 3 * 243);
+        assert_eq!(tower.find_correction(), Ok(None));
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let tower = tower("r (1) -> x, y, z\nx (1) -> a, b\na (2)\nb (3)\ny (7)\nz (7)");
+        assert_eq!(tower.to_dot(), "digraph tower {
+    node [shape=box];
+    \"r\" [label=\"r\\nweight 1\\ntotal 21\", color=red];
+    \"x\" [label=\"x\\nweight 1\\ntotal 6\", color=red];
+    \"y\" [label=\"y\\nweight 7\\ntotal 7\"];
+    \"z\" [label=\"z\\nweight 7\\ntotal 7\"];
+    \"a\" [label=\"a\\nweight 2\\ntotal 2\", style=filled, fillcolor=orange];
+    \"b\" [label=\"b\\nweight 3\\ntotal 3\"];
+    \"r\" -> \"x\";
+    \"r\" -> \"y\";
+    \"r\" -> \"z\";
+    \"x\" -> \"a\";
+    \"x\" -> \"b\";
+}
+");
+    }
+
+    #[test]
+    fn test_to_dot_undefined() {
+        let dot = tower("a (1) -> b").to_dot();
+        assert!(dot.contains("\"a\" -> \"b\";"), "{}", dot);
+        assert!(dot.contains("\"b\" [label=\"b\\nweight ?\\ntotal 0\"];"), "{}", dot);
+    }
+
+    #[rstest(depth, expected,
+    case(None, "tknk (41) 778 unbalanced
+|-- ugml (68) 251
+|   |-- gyxo (61) 61
+|   |-- ebii (61) 61
+|   `-- jptl (61) 61
+|-- padx (45) 243
+|   |-- pbga (66) 66
+|   |-- havc (66) 66
+|   `-- qoyq (66) 66
+`-- fwft (72) 243
+    |-- ktlj (57) 57
+    |-- cntj (57) 57
+    `-- xhth (57) 57
+"),
+    case(Some(1), "tknk (41) 778 unbalanced
+|-- ugml (68) 251 [3 children hidden]
+|-- padx (45) 243 [3 children hidden]
+`-- fwft (72) 243 [3 children hidden]
+"),
+    case(Some(0), "tknk (41) 778 unbalanced [3 children hidden]\n"),
+    )]
+    fn test_to_tree(depth: Option<usize>, expected: &str) {
+        let tower = Tower::from_file("day_07/test.txt").unwrap();
+        assert_eq!(tower.to_tree(depth), expected);
+    }
+
+    #[test]
+    fn test_to_tree_deep() {
+        let tower = tower("a (1) -> b\nb (1) -> c, d\nc (1) -> e\nd (2)\ne (1)");
+        assert_eq!(tower.to_tree(None), "a (1) 6\n`-- b (1) 5\n    |-- c (1) 2\n    |   `-- e (1) 1\n    `-- d (2) 2\n");
+    }
+
+    #[rstest(args,
+    case(&["--depth"]),
+    case(&["--tree", "--depth", "deep"]),
+    case(&["--depth", "2"]),
+    case(&["--dot", "--tree"]),
+    case(&["--tree", "--edit"]),
+    case(&["--write", "yaml"]),
+    case(&["--write"]),
+    case(&["--edit", "--write", "json"]),
+    case(&["--graph"]),
+    )]
+    fn test_options_error(args: &[&str]) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        assert!(Options::from_args(&args).is_err());
+    }
+
+    // the totals kept up to date by the edits match counting them again from scratch
+    fn assert_totals(tower: &mut Tower) {
+        let totals: Vec<u32> = tower.programs.iter().map(|p| p.total_weight).collect();
+        tower.update_totals();
+        let expected: Vec<u32> = tower.programs.iter().map(|p| p.total_weight).collect();
+        assert_eq!(totals, expected);
+        assert_eq!(tower.validate(), Vec::new());
+    }
+
+    #[test]
+    fn test_set_weight() {
+        let mut tower = Tower::from_file("day_07/input.txt").unwrap();
+        assert!(!tower.is_balanced());
+        tower.set_weight("kiatxq", 1226).unwrap();
+        assert!(tower.is_balanced());
+        assert_eq!(tower.find_correction(), Ok(None));
+        assert_totals(&mut tower);
+        assert_eq!(tower.set_weight("nope", 1), Err(EditError::Unknown("nope".to_string())));
+    }
+
+    #[test]
+    fn test_add_program() {
+        let mut tower = tower("a (1) -> b, c\nb (2)\nc (2)");
+        assert!(tower.is_balanced());
+        tower.add_program("d", 3, Some("b")).unwrap();
+        assert!(!tower.is_balanced());
+        assert_eq!(tower[tower.id("a").unwrap()].total_weight, 8);
+        tower.add_program("e", 3, Some("c")).unwrap();
+        assert!(tower.is_balanced());
+        assert_totals(&mut tower);
+
+        // at the bottom
+        tower.add_program("z", 4, None).unwrap();
+        assert_eq!(tower.root_name(), Some("z"));
+        assert_eq!(tower[tower.id("z").unwrap()].total_weight, 15);
+        assert_totals(&mut tower);
+
+        assert_eq!(tower.add_program("b", 1, Some("a")), Err(EditError::Exists("b".to_string())));
+        assert_eq!(tower.add_program("f", 1, Some("x")), Err(EditError::Unknown("x".to_string())));
+        assert_eq!(tower.id("f"), None);
+    }
+
+    #[test]
+    fn test_add_to_empty() {
+        let mut tower = Tower::new();
+        assert!(tower.is_balanced());
+        tower.add_program("a", 3, None).unwrap();
+        tower.add_program("b", 4, Some("a")).unwrap();
+        assert_eq!(tower.root_name(), Some("a"));
+        assert_totals(&mut tower);
+    }
+
+    #[test]
+    fn test_edit_overflow() {
+        let mut tower = Tower::from_file("day_07/test.txt").unwrap();
+        let text = tower.to_text();
+        let totals: Vec<u32> = tower.programs.iter().map(|p| p.total_weight).collect();
+        let overflow = |name: &str| Err(EditError::Overflow(name.to_string()));
+
+        assert_eq!(tower.set_weight("ugml", u32::MAX), overflow("ugml"));
+        assert_eq!(tower.set_weight("pbga", u32::MAX - 100), overflow("padx"));
+        assert_eq!(tower.add_program("big", u32::MAX, Some("gyxo")), overflow("gyxo"));
+        assert_eq!(tower.add_program("big", u32::MAX, None), overflow("big"));
+        assert_eq!(tower.id("big"), None);
+
+        assert_eq!(tower.to_text(), text);
+        assert_eq!(tower.programs.iter().map(|p| p.total_weight).collect::<Vec<u32>>(), totals);
+    }
+
+    #[test]
+    fn test_remove_program() {
+        let mut tower = Tower::from_file("day_07/test.txt").unwrap();
+        let before: Vec<(String, ProgramId)> = tower.program_ids().map(|id| (tower[id].name.clone(), id)).collect();
+        tower.remove_program("ugml").unwrap();
+        assert!(tower.is_balanced());
+        for name in &["ugml", "gyxo", "ebii", "jptl"] {
+            assert_eq!(tower.id(name), None);
+        }
+        assert_eq!(tower.programs.len(), 9);
+        // only the programs that were past the end fill the gaps
+        for (name, id) in &before {
+            if id.0 < 9 && tower.id(name).is_some() {
+                assert_eq!(tower.id(name), Some(*id));
+            }
+        }
+        assert_eq!(tower.root_name(), Some("tknk"));
+        assert_eq!(tower[tower.root.unwrap()].total_weight, 41 + 243 + 243);
+        assert_totals(&mut tower);
+        for id in tower.program_ids() {
+            assert_eq!(tower.id(&tower[id].name), Some(id));
+        }
+
+        tower.remove_program("qoyq").unwrap();
+        assert!(!tower.is_balanced());
+        assert_totals(&mut tower);
+
+        tower.remove_program("tknk").unwrap();
+        assert_eq!(tower.root, None);
+        assert!(tower.programs.is_empty());
+        assert_eq!(tower.remove_program("tknk"), Err(EditError::Unknown("tknk".to_string())));
+    }
+
+    #[test]
+    fn test_reparent() {
+        let mut tower = Tower::from_file("day_07/test.txt").unwrap();
+        tower.reparent("gyxo", "padx").unwrap();
+        assert_eq!(tower[tower.id("gyxo").unwrap()].parent, tower.id("padx"));
+        assert_eq!(tower[tower.id("padx").unwrap()].children.last(), tower.id("gyxo").as_ref());
+        assert_eq!(tower[tower.id("ugml").unwrap()].total_weight, 68 + 61 + 61);
+        assert_eq!(tower[tower.id("padx").unwrap()].total_weight, 243 + 61);
+        assert_totals(&mut tower);
+
+        let error = |name: &str, parent: &str| EditError::Loop{name: name.to_string(), parent: parent.to_string()};
+        assert_eq!(tower.reparent("ugml", "ugml"), Err(error("ugml", "ugml")));
+        assert_eq!(tower.reparent("ugml", "jptl"), Err(error("ugml", "jptl")));
+        assert_eq!(tower.reparent("tknk", "ktlj"), Err(error("tknk", "ktlj")));
+        assert_eq!(tower.reparent("ugml", "nope"), Err(EditError::Unknown("nope".to_string())));
+        assert_totals(&mut tower);
+    }
+
+    #[test]
+    fn test_random_edits() {
+        let mut random = Random::new(7);
+        let mut tower = Tower::from_file("day_07/test_3.txt").unwrap();
+        let mut next_name = 0;
+        for _ in 0..300 {
+            let names: Vec<String> = tower.programs.iter().map(|p| p.name.clone()).collect();
+            let edit = match (names.len(), random.below(5)) {
+                (0, _) | (_, 0) => {
+                    next_name += 1;
+                    let parent = if names.is_empty() || random.below(10) == 0 { None } else { Some(names[random.below(names.len())].clone()) };
+                    Edit::Add{name: format!("n{}", next_name), weight: random.between(&(0..=99)), parent}
+                },
+                (len, 1) => Edit::SetWeight{name: names[random.below(len)].clone(), weight: random.between(&(0..=99))},
+                (len, 2) if random.below(4) == 0 => Edit::Remove{name: names[random.below(len)].clone()},
+                (len, _) => Edit::Move{name: names[random.below(len)].clone(), parent: names[random.below(len)].clone()},
+            };
+            match tower.apply(&edit) {
+                Ok(()) | Err(EditError::Loop{..}) => {},
+                Err(e) => panic!("{:?}: {}", edit, e),
+            }
+            assert_totals(&mut tower);
+        }
+    }
+
+    #[rstest(line, expected,
+    case("set a 3", Ok(Edit::SetWeight{name: "a".to_string(), weight: 3})),
+    case("  add b 4 ", Ok(Edit::Add{name: "b".to_string(), weight: 4, parent: None})),
+    case("add b 4 a", Ok(Edit::Add{name: "b".to_string(), weight: 4, parent: Some("a".to_string())})),
+    case("remove\tb", Ok(Edit::Remove{name: "b".to_string()})),
+    case("move b a", Ok(Edit::Move{name: "b".to_string(), parent: "a".to_string()})),
+    case("set a -3", Err("invalid weight -3".to_string())),
+    case("move b", Err("unknown edit 'move b'".to_string())),
+    case("delete b", Err("unknown edit 'delete b'".to_string())),
+    )]
+    fn test_edit_parse(line: &str, expected: Result<Edit, String>) {
+        assert_eq!(Edit::parse(line), expected);
+    }
+
+    #[test]
+    fn test_edit_error_display() {
+        assert_eq!(EditError::Loop{name: "a".to_string(), parent: "b".to_string()}.to_string(),
+                   "a cannot stand on b, it is above a");
+        assert_eq!(EditError::Exists("a".to_string()).to_string(), "a is already in the tower");
+        assert_eq!(EditError::Overflow("a".to_string()).to_string(), "a would weigh too much");
+    }
+
+    #[rstest(path,
+    case(&"day_07/test.txt"),
+    case(&"day_07/test_2.txt"),
+    case(&"day_07/test_3.txt"),
+    case(&"day_07/input.txt"),
+    )]
+    fn test_to_text_round_trip(path: &str) {
+        let tower = Tower::from_file(path).unwrap();
+        let text = tower.to_text();
+        assert_eq!(text.lines().count(), tower.programs.len());
+        let loaded = Tower::from_reader(text.as_bytes()).unwrap();
+        assert_eq!(loaded.validate(), Vec::new());
+        assert_eq!(loaded.to_text(), text);
+        assert_eq!(loaded.to_tree(None), tower.to_tree(None));
+
+        // the same tower in another order is written the same way
+        let mut lines: Vec<&str> = text.lines().collect();
+        lines.reverse();
+        assert_eq!(Tower::from_reader(lines.join("\n").as_bytes()).unwrap().to_text(), text);
+        assert_eq!(json::from_json(&json::to_json(&tower)).unwrap().to_text(), text);
+    }
+
+    #[test]
+    fn test_to_text() {
+        let mut tower = tower("c (3)\nb (2) -> d\na (1) -> b, c\nd (4)");
+        assert_eq!(tower.to_text(), "a (1) -> b, c\nb (2) -> d\nd (4)\nc (3)\n");
+        tower.add_program("e", 5, Some("c")).unwrap();
+        tower.remove_program("b").unwrap();
+        assert_eq!(tower.to_text(), "a (1) -> c\nc (3) -> e\ne (5)\n");
+        assert_eq!(Tower::new().to_text(), "");
+        // never defined
+        assert_eq!(Tower::from_reader("a (1) -> b".as_bytes()).unwrap().to_text(), "a (1) -> b\n");
+    }
+
+    #[rstest(args, fault,
+    case(&["--generate"], None),
+    case(&["--generate", "--depth", "2", "--branching", "3", "--weights", "5-9", "--seed", "4"], None),
+    case(&["--generate", "--depth", "4", "--branching", "1-3", "--fault", "2"], Some(2)),
+    )]
+    fn test_generate_options(args: &[&str], fault: Option<usize>) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let mut options = GenerateOptions::from_args(&args).unwrap();
+        assert_eq!(options.fault, fault);
+        let generated = options.generator.generate(options.fault).unwrap();
+        let tower = tower(&generated.text);
+        assert_eq!(tower.root_name(), Some(generated.root.as_str()));
+        assert_eq!(tower.find_correction(), Ok(generated.correction));
+    }
+
+    #[rstest(args,
+    case(&["--generate", "--depth"]),
+    case(&["--generate", "--depth", "-1"]),
+    case(&["--generate", "--branching", "4-2"]),
+    case(&["--generate", "--weights", "a-b"]),
+    case(&["--generate", "--fault", "0"]),
+    case(&["--generate", "--depth", "2", "--fault", "3"]),
+    case(&["--generate", "--tree"]),
+    )]
+    fn test_generate_options_error(args: &[&str]) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        assert!(GenerateOptions::from_args(&args).is_err());
+    }
 }
@@ -0,0 +1,279 @@
+use core::fmt;
+use std::error::Error;
+
+/// A program as described by one line of the puzzle input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub weight: u32,
+    pub children: Vec<String>,
+}
+
+/// What the parser was looking for when it found something else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Name,
+    OpenParen,
+    Weight,
+    CloseParen,
+    /// `->` or the end of the line, after the weight
+    ArrowOrEnd,
+    /// `,` or the end of the line, after a child
+    CommaOrEnd,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Name => write!(f, "a name"),
+            Token::OpenParen => write!(f, "'('"),
+            Token::Weight => write!(f, "a weight"),
+            Token::CloseParen => write!(f, "')'"),
+            Token::ArrowOrEnd => write!(f, "'->' or the end of the line"),
+            Token::CommaOrEnd => write!(f, "',' or the end of the line"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// `found` is None when the line ended too soon
+    Expected { expected: Token, found: Option<char> },
+    /// the weight does not fit in a u32
+    WeightOverflow,
+}
+
+/// Where and why a line does not follow the grammar, columns are counted in characters from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    pub column: usize,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ErrorKind::Expected { expected, found: Some(c) } =>
+                write!(f, "column {}: expected {} but found {:?}", self.column, expected, c),
+            ErrorKind::Expected { expected, found: None } =>
+                write!(f, "column {}: expected {} but the line ended", self.column, expected),
+            ErrorKind::WeightOverflow =>
+                write!(f, "column {}: the weight is too large", self.column),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// A `ParseError` in a whole input, lines are counted from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineError {
+    pub line: usize,
+    pub error: ParseError,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, {}", self.line, self.error)
+    }
+}
+
+impl Error for LineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Parse a line like `name (weight) -> child, child`, the arrow and the children are optional.
+///
+/// Names are made of letters, digits and `_`. Whitespace is allowed between any two tokens
+/// and required nowhere. None for a blank line; anything after a `#` is a comment.
+pub fn parse_line(line: &str) -> Result<Option<Entry>, ParseError> {
+    let mut parser = Parser{
+        chars: line.chars().collect(),
+        pos: 0,
+    };
+    parser.skip_whitespace();
+    if parser.at_end() {
+        return Ok(None);
+    }
+
+    // line := name '(' weight ')' ('->' name (',' name)*)?
+    let name = parser.name()?;
+    parser.expect('(', Token::OpenParen)?;
+    let weight = parser.weight()?;
+    parser.expect(')', Token::CloseParen)?;
+    let mut children = Vec::new();
+    if parser.consume_arrow() {
+        children.push(parser.name()?);
+        while parser.consume(',') {
+            children.push(parser.name()?);
+        }
+        parser.skip_whitespace();
+        if !parser.at_end() {
+            return Err(parser.error(Token::CommaOrEnd));
+        }
+    } else {
+        parser.skip_whitespace();
+        if !parser.at_end() {
+            return Err(parser.error(Token::ArrowOrEnd));
+        }
+    }
+    Ok(Some(Entry{name, weight, children}))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error(&self, expected: Token) -> ParseError {
+        ParseError{
+            column: self.pos + 1,
+            kind: ErrorKind::Expected { expected, found: self.peek() },
+        }
+    }
+
+    // a comment ends the line too
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned().filter(|&c| c != '#')
+    }
+
+    fn at_end(&self) -> bool {
+        self.peek().is_none()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn consume(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn consume_arrow(&mut self) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some('-') && self.chars.get(self.pos + 1) == Some(&'>') {
+            self.pos += 2;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char, token: Token) -> Result<(), ParseError> {
+        if self.consume(expected) {
+            Ok(())
+        } else {
+            Err(self.error(token))
+        }
+    }
+
+    // the longest run of characters matching `accept`, may be empty
+    fn take_while(&mut self, accept: impl Fn(char) -> bool) -> String {
+        self.skip_whitespace();
+        let mut rv = String::new();
+        while let Some(c) = self.peek().filter(|&c| accept(c)) {
+            rv.push(c);
+            self.pos += 1;
+        }
+        rv
+    }
+
+    fn name(&mut self) -> Result<String, ParseError> {
+        let rv = self.take_while(|c| c.is_alphanumeric() || c == '_');
+        if rv.is_empty() {
+            return Err(self.error(Token::Name));
+        }
+        Ok(rv)
+    }
+
+    fn weight(&mut self) -> Result<u32, ParseError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let digits = self.take_while(|c| c.is_ascii_digit());
+        if digits.is_empty() {
+            return Err(self.error(Token::Weight));
+        }
+        // only digits are left, so a failure is an overflow
+        digits.parse().map_err(|_| ParseError{column: start + 1, kind: ErrorKind::WeightOverflow})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn entry(name: &str, weight: u32, children: &[&str]) -> Option<Entry> {
+        Some(Entry{
+            name: name.to_string(),
+            weight,
+            children: children.iter().map(|c| c.to_string()).collect(),
+        })
+    }
+
+    #[rstest(line, expected,
+    case("pbga (66)", entry("pbga", 66, &[])),
+    case("fwft (72) -> ktlj, cntj, xhth", entry("fwft", 72, &["ktlj", "cntj", "xhth"])),
+    case("fwft(72)->ktlj,cntj", entry("fwft", 72, &["ktlj", "cntj"])),
+    case("\tfwft\t( 72 )  ->\tktlj ,cntj  ", entry("fwft", 72, &["ktlj", "cntj"])),
+    case("a_1 (0) # the smallest", entry("a_1", 0, &[])),
+    case("a (1) -> b# no space", entry("a", 1, &["b"])),
+    case("a (4294967295)", entry("a", u32::MAX, &[])),
+    case("", None),
+    case("   \t ", None),
+    case("# a comment", None),
+    case("  # (1) -> b", None),
+    )]
+    fn test_parse_line(line: &str, expected: Option<Entry>) {
+        assert_eq!(parse_line(line), Ok(expected));
+    }
+
+    #[rstest(line, column, expected, found,
+    case("(7)", 1, Token::Name, Some('(')),
+    case("a 7)", 3, Token::OpenParen, Some('7')),
+    case("a", 2, Token::OpenParen, None),
+    case("a (7", 5, Token::CloseParen, None),
+    case("a (7 -> b", 6, Token::CloseParen, Some('-')),
+    case("a ()", 4, Token::Weight, Some(')')),
+    case("a (-7)", 4, Token::Weight, Some('-')),
+    case("a (7.5)", 5, Token::CloseParen, Some('.')),
+    case("a (7) b", 7, Token::ArrowOrEnd, Some('b')),
+    case("a (7) - b", 7, Token::ArrowOrEnd, Some('-')),
+    case("a (7) (8)", 7, Token::ArrowOrEnd, Some('(')),
+    case("a (7) ->", 9, Token::Name, None),
+    case("a (7) -> # no child", 10, Token::Name, None),
+    case("a (7) -> b,", 12, Token::Name, None),
+    case("a (7) -> b,, c", 12, Token::Name, Some(',')),
+    case("a (7) -> b c", 12, Token::CommaOrEnd, Some('c')),
+    case("a (7) -> b -> c", 12, Token::CommaOrEnd, Some('-')),
+    )]
+    fn test_parse_line_error(line: &str, column: usize, expected: Token, found: Option<char>) {
+        let kind = ErrorKind::Expected { expected, found };
+        assert_eq!(parse_line(line), Err(ParseError{column, kind}));
+    }
+
+    #[test]
+    fn test_weight_overflow() {
+        let error = parse_line("abc ( 4294967296)").unwrap_err();
+        assert_eq!(error, ParseError{column: 7, kind: ErrorKind::WeightOverflow});
+        assert_eq!(error.to_string(), "column 7: the weight is too large");
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(parse_line("a (7").unwrap_err().to_string(), "column 5: expected ')' but the line ended");
+        assert_eq!(parse_line("a (7) b").unwrap_err().to_string(),
+                   "column 7: expected '->' or the end of the line but found 'b'");
+    }
+}
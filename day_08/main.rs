@@ -126,7 +126,7 @@ struct CompOperation {
 impl CompOperation {
     fn new(register: &str, op: Op, value: i32) -> CompOperation {
         CompOperation{
-            operation: Operation{register: register.to_string(), operation: op, value: value},
+            operation: Operation{register: register.to_string(), operation: op, value},
             comparison: Operation::new_nop(),
         }
     }
@@ -146,7 +146,6 @@ impl OperationTrait for CompOperation {
 struct Program {
     registers: HashMap<String, i32>,
     code: Vec<CompOperation>,
-    code_ptr: usize,
 }
 
 impl Program {
@@ -154,7 +153,6 @@ impl Program {
         Program{
             registers: HashMap::new(),
             code: Vec::new(),
-            code_ptr: 0,
         }
     }
 
@@ -164,7 +162,7 @@ impl Program {
         for l in lines {
             let entries: Vec<&str> = l.split_whitespace().collect();
 
-            let register = entries.get(0)?;
+            let register = entries.first()?;
             let op = Op::from_string(entries.get(1)?);
             let value = entries.get(2)?.parse().unwrap_or(0);
 
@@ -227,6 +225,4 @@ fn main() {
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use rstest::rstest;
 }
@@ -1,7 +1,6 @@
 use std::{io, env};
 use std::fs::File;
 use std::io::{BufReader, BufRead};
-use std::iter::FromIterator;
 
 
 fn read(path: &str) -> io::Result<Vec<String>> {